@@ -0,0 +1,206 @@
+//! Choice of the graphics backend and the adapter to render with
+
+use std::fmt::Write;
+
+use anyhow::bail;
+
+/// Backends searched without --backend, adapters of the primary ones are preferred over GL
+pub const DEFAULT_BACKENDS: wgpu::Backends = wgpu::Backends::PRIMARY.union(wgpu::Backends::GL);
+
+/// Graphics API, also accepting the names `WGPU_BACKEND` takes
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    #[value(alias = "vk")]
+    Vulkan,
+    #[value(alias = "opengl", alias = "gles")]
+    Gl,
+    #[value(alias = "mtl")]
+    Metal,
+    #[value(alias = "d3d12")]
+    Dx12,
+}
+
+impl Backend {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerPreference {
+    /// Integrated GPU
+    Low,
+    /// Discrete GPU
+    High,
+}
+
+impl PowerPreference {
+    fn device_type(self) -> wgpu::DeviceType {
+        match self {
+            PowerPreference::Low => wgpu::DeviceType::IntegratedGpu,
+            PowerPreference::High => wgpu::DeviceType::DiscreteGpu,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// Position in the --list-adapters output
+    Index(usize),
+    /// Case insensitive part of the adapter name
+    Name(String),
+}
+
+impl AdapterSelector {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            return Err("adapter must be an index or a part of its name".into());
+        }
+
+        match s.parse() {
+            Ok(index) => Ok(AdapterSelector::Index(index)),
+            Err(_) => Ok(AdapterSelector::Name(s.to_lowercase())),
+        }
+    }
+
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            AdapterSelector::Index(i) => *i == index,
+            AdapterSelector::Name(name) => info.name.to_lowercase().contains(name),
+        }
+    }
+}
+
+impl std::fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AdapterSelector::Index(i) => write!(f, "{i}"),
+            AdapterSelector::Name(name) => write!(f, "\"{name}\""),
+        }
+    }
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct AdapterOptions {
+    /// Graphics APIs to look for adapters in, comma separated. All of them by default
+    #[arg(long, value_enum, value_delimiter = ',', env = "WGPU_BACKEND")]
+    pub backend: Vec<Backend>,
+
+    /// Adapter to render with, by its index in --list-adapters or a part of its name
+    #[arg(long, value_name = "INDEX|NAME", value_parser = AdapterSelector::parse, env = "WGPU_ADAPTER_NAME")]
+    pub adapter: Option<AdapterSelector>,
+
+    /// Prefer the integrated (low) or the discrete (high) GPU when several adapters fit
+    #[arg(long, value_enum)]
+    pub power_preference: Option<PowerPreference>,
+
+    /// Print the adapters of the selected backends with their features and limits, then exit
+    #[arg(long)]
+    pub list_adapters: bool,
+}
+
+impl AdapterOptions {
+    pub fn backends(&self) -> wgpu::Backends {
+        match self.backend.as_slice() {
+            [] => DEFAULT_BACKENDS,
+            backends => backends.iter().fold(wgpu::Backends::empty(), |all, b| all | b.backends()),
+        }
+    }
+
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            ..wgpu::InstanceDescriptor::from_env_or_default()
+        })
+    }
+
+    /// Picks the adapter, which has to be able to present to `surface` when given
+    pub fn select(&self, instance: &wgpu::Instance, surface: Option<&wgpu::Surface>) -> anyhow::Result<wgpu::Adapter> {
+        let mut adapters = instance.enumerate_adapters(self.backends());
+        let infos: Vec<wgpu::AdapterInfo> = adapters.iter().map(|a| a.get_info()).collect();
+
+        let index = self.choose(&infos, |i| surface.is_none_or(|s| adapters[i].is_surface_supported(s)))?;
+        Ok(adapters.swap_remove(index))
+    }
+
+    /// Index of the adapter to use among `adapters`, `usable` tells whether the one at an index
+    /// can present. Without --adapter, discrete and integrated GPUs go before software ones and
+    /// the primary backends before GL
+    pub fn choose(&self, adapters: &[wgpu::AdapterInfo], usable: impl Fn(usize) -> bool) -> anyhow::Result<usize> {
+        if adapters.is_empty() {
+            bail!("No adapters found for backends {:?}, check the GPU drivers or try another --backend", self.backends());
+        }
+
+        let matching: Vec<usize> = (0..adapters.len())
+            .filter(|&i| self.adapter.as_ref().is_none_or(|selector| selector.matches(i, &adapters[i])))
+            .collect();
+        if let Some(selector) = &self.adapter
+            && matching.is_empty()
+        {
+            bail!("No adapter matches --adapter {selector}, the available ones are:\n{}", describe_all(adapters.iter().enumerate()));
+        }
+
+        let rank = |info: &wgpu::AdapterInfo| {(
+            self.power_preference.is_some_and(|p| p.device_type() != info.device_type),
+            info.device_type == wgpu::DeviceType::Cpu,
+            self.backend.is_empty() && info.backend == wgpu::Backend::Gl,
+        )};
+
+        match matching.iter().copied().filter(|&i| usable(i)).min_by_key(|&i| rank(&adapters[i])) {
+            Some(index) => Ok(index),
+            None => bail!(
+                "None of these adapters can present to the window:\n{}",
+                describe_all(matching.into_iter().map(|i| (i, &adapters[i]))),
+            ),
+        }
+    }
+}
+
+/// One line summary of an adapter
+pub fn describe(info: &wgpu::AdapterInfo) -> String {
+    format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+}
+
+/// Numbered lines of `describe`
+fn describe_all<'a>(adapters: impl IntoIterator<Item = (usize, &'a wgpu::AdapterInfo)>) -> String {
+    adapters
+        .into_iter()
+        .map(|(i, info)| format!("  {i}: {}", describe(info)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What --list-adapters prints: every adapter with its info, features and limits,
+/// numbered as --adapter takes them
+pub fn list_adapters(adapters: &[wgpu::Adapter]) -> String {
+    if adapters.is_empty() {
+        return "No adapters found\n".into();
+    }
+
+    let mut text = String::new();
+    for (i, adapter) in adapters.iter().enumerate() {
+        let info = adapter.get_info();
+        let _ = writeln!(text, "{i}: {}", info.name);
+        let _ = writeln!(text, "  backend: {:?}", info.backend);
+        let _ = writeln!(text, "  type: {:?}", info.device_type);
+        let _ = writeln!(text, "  vendor: {:#06x}, device: {:#06x}", info.vendor, info.device);
+        let driver = [info.driver.as_str(), info.driver_info.as_str()].join(" ");
+        let _ = writeln!(text, "  driver: {}", driver.trim());
+
+        let features: Vec<&str> = adapter.features().iter_names().map(|(name, _)| name).collect();
+        let _ = writeln!(text, "  features: {}", if features.is_empty() { "none".into() } else { features.join(", ") });
+
+        // Debug output of Limits is one field per line between the braces
+        let _ = writeln!(text, "  limits:");
+        let limits = format!("{:#?}", adapter.limits());
+        for line in limits.lines().filter(|line| line.starts_with(' ')) {
+            let _ = writeln!(text, "    {}", line.trim().trim_end_matches(','));
+        }
+    }
+    text
+}
@@ -0,0 +1,277 @@
+//! Bloom effect of the post stack: the bright parts of the input are thresholded into the
+//! first mip of a half resolution HDR texture, progressively downsampled through its mip chain,
+//! upsampled back with a tent filter, adding every level on the way, and composited over the input
+
+use crate::{
+    hot_reload::Shader,
+    post::{EffectUniforms, POST_TEXTURE_FORMAT},
+    renderer::{texture_bind_group_layout, PipelineBuilder, PipelineError, Vertex, QUAD},
+};
+
+/// Format of the mip chain, so the sum of the levels doesn't clip
+pub const BLOOM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub const MAX_BLOOM_LEVELS: u32 = 8;
+
+/// Uniform slots used by a chain of `MAX_BLOOM_LEVELS`: prefilter, downsamples, upsamples and composite
+pub const BLOOM_STAGES: usize = 2 * MAX_BLOOM_LEVELS as usize;
+
+/// Matches the `STAGE_*` constants in `shader_bloom.wgsl`
+const STAGE_PREFILTER: u32 = 0;
+const STAGE_DOWNSAMPLE: u32 = 1;
+const STAGE_UPSAMPLE: u32 = 2;
+const STAGE_COMPOSITE: u32 = 3;
+
+/// Number of mips for an input of `size`, the `requested` number limited so the smallest mip is at least 2x2
+pub fn bloom_levels(size: (u32, u32), requested: f32) -> u32 {
+    let half = (size.0 / 2).min(size.1 / 2).max(1);
+    let max_levels = half.ilog2().max(1);
+    (requested.round().max(1.0) as u32).min(max_levels).min(MAX_BLOOM_LEVELS)
+}
+
+fn mip_size(size: (u32, u32), level: u32) -> (u32, u32) {
+    ((size.0 >> (level + 1)).max(1), (size.1 >> (level + 1)).max(1))
+}
+
+fn texel_size(size: (u32, u32)) -> [f32; 2] {
+    [1.0 / size.0.max(1) as f32, 1.0 / size.1.max(1) as f32]
+}
+
+struct BloomPipelines {
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+/// The composite draws into `POST_TEXTURE_FORMAT` like every other effect of the post stack
+fn bloom_pipelines(
+    device: &wgpu::Device,
+    cache: Option<&wgpu::PipelineCache>,
+    layouts: &[wgpu::BindGroupLayout; 3],
+    source: &str,
+) -> Result<BloomPipelines, PipelineError> {
+    let builder = |format| {
+        PipelineBuilder::new(device, format, source)
+            .with_label(Shader::Bloom.file_name())
+            .with_cache(cache)
+            .with_buffer(Vertex::desc())
+            .with_bind_group(&layouts[0])
+            .with_bind_group(&layouts[1])
+            .with_bind_group(&layouts[2])
+    };
+
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    Ok(BloomPipelines {
+        downsample: builder(BLOOM_FORMAT).build()?,
+        upsample: builder(BLOOM_FORMAT).with_blending(wgpu::BlendState { color: additive, alpha: additive }).build()?,
+        composite: builder(POST_TEXTURE_FORMAT).build()?,
+    })
+}
+
+struct Mip {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+}
+
+/// Mip chain and pipelines of the bloom effect, drawn by `PostStack`
+pub struct BloomChain {
+    pipelines: BloomPipelines,
+    cache: Option<wgpu::PipelineCache>,
+    layouts: [wgpu::BindGroupLayout; 3],
+
+    texture: Option<wgpu::Texture>,
+    mips: Vec<Mip>,
+    size: (u32, u32),
+}
+
+impl BloomChain {
+    /// `stage_layout` is the layout of the dynamically offset `EffectUniforms`
+    pub fn new(
+        device: &wgpu::Device,
+        cache: Option<&wgpu::PipelineCache>,
+        stage_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self, PipelineError> {
+        let texture_layout = texture_bind_group_layout(device);
+        let layouts = [texture_layout.clone(), stage_layout.clone(), texture_layout];
+
+        Ok(BloomChain {
+            pipelines: bloom_pipelines(device, cache, &layouts, Shader::Bloom.builtin())?,
+            layouts,
+            cache: cache.cloned(),
+            texture: None,
+            mips: vec![],
+            size: (0, 0),
+        })
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.mips.len() as u32
+    }
+
+    /// Makes sure the mip chain fits an input of `size` with the `levels` requested
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32), levels: f32) {
+        let levels = bloom_levels(size, levels);
+        if self.size == size && self.levels() == levels {
+            return;
+        }
+
+        if let Some(texture) = self.texture.take() {
+            texture.destroy();
+        }
+
+        let base = mip_size(size, 0);
+        let texture = device.create_texture(&wgpu::wgt::TextureDescriptor {
+            label: Some("bloom_texture"),
+            size: wgpu::Extent3d {
+                width: base.0,
+                height: base.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BLOOM_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor {
+            label: Some("bloom_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        self.mips = (0..levels)
+            .map(|level| {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("bloom_mip"),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                });
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("bloom_mip_bind_group"),
+                    layout: &self.layouts[0],
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                });
+
+                Mip { view, bind_group, size: mip_size(size, level) }
+            })
+            .collect();
+
+        self.texture = Some(texture);
+        self.size = size;
+    }
+
+    /// Uniforms of every stage, in the order `record` uses them. `effect` holds the bloom
+    /// parameters and the input orientation, `prepare` must have been called first
+    pub fn stages(&self, effect: &EffectUniforms) -> Vec<EffectUniforms> {
+        let levels = self.levels();
+        let [threshold, intensity, radius, _] = effect.params;
+        let params = [threshold, intensity, radius, levels as f32];
+        let stage = |kind, flip_y, source_size| EffectUniforms { kind, flip_y, texel_size: texel_size(source_size), params };
+
+        let mut stages = vec![stage(STAGE_PREFILTER, effect.flip_y, self.size)];
+        for level in 1..levels {
+            stages.push(stage(STAGE_DOWNSAMPLE, 0, self.mips[level as usize - 1].size));
+        }
+        for level in (1..levels).rev() {
+            stages.push(stage(STAGE_UPSAMPLE, 0, self.mips[level as usize].size));
+        }
+        stages.push(stage(STAGE_COMPOSITE, effect.flip_y, self.mips[0].size));
+
+        stages
+    }
+
+    /// Draws the mip chain from `input`. `stage_offsets` are the dynamic offsets of `stages`
+    /// in `stage_bind_group`, the last one being used by `composite`
+    pub fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        vertex_buffer: &wgpu::Buffer,
+        input: &wgpu::BindGroup,
+        stage_bind_group: &wgpu::BindGroup,
+        stage_offsets: &[u32],
+    ) {
+        let levels = self.mips.len();
+
+        // Prefilter and downsamples: input -> mip 0 -> mip 1 -> ...
+        // Upsamples, adding onto what the downsamples left: ... -> mip 1 -> mip 0
+        let downsamples = (0..levels).map(|level| {
+            let source = if level == 0 { input } else { &self.mips[level - 1].bind_group };
+            (source, level, &self.pipelines.downsample, true)
+        });
+        let upsamples = (1..levels).rev().map(|level| {
+            (&self.mips[level].bind_group, level - 1, &self.pipelines.upsample, false)
+        });
+
+        for ((source, target, pipeline, clear), &offset) in downsamples.chain(upsamples).zip(stage_offsets) {
+            let load = if clear { wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT) } else { wgpu::LoadOp::Load };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Bloom render pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &self.mips[target].view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_bind_group(0, source, &[]);
+            render_pass.set_bind_group(1, stage_bind_group, &[offset]);
+            // Unused until the composite, any texture not drawn to in this pass will do
+            render_pass.set_bind_group(2, input, &[]);
+            render_pass.draw(0..QUAD.len() as u32, 0..1);
+        }
+    }
+
+    /// Draws `input` with the bloom added into `render_pass`
+    pub fn composite(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        vertex_buffer: &wgpu::Buffer,
+        input: &wgpu::BindGroup,
+        stage_bind_group: &wgpu::BindGroup,
+        stage_offset: u32,
+    ) {
+        render_pass.set_pipeline(&self.pipelines.composite);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, input, &[]);
+        render_pass.set_bind_group(1, stage_bind_group, &[stage_offset]);
+        render_pass.set_bind_group(2, &self.mips[0].bind_group, &[]);
+        render_pass.draw(0..QUAD.len() as u32, 0..1);
+    }
+
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        if shader == Shader::Bloom {
+            self.pipelines = bloom_pipelines(device, self.cache.as_ref(), &self.layouts, source)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Animation time, decoupled from the wall clock so it can be paused, scaled, scrubbed and stepped
+
+use std::time::{Duration, Instant};
+
+/// Step of `Clock::step` without a fixed timestep
+pub const DEFAULT_STEP: f64 = 1.0 / 60.0;
+
+/// Most wall time a single tick accounts for, so a stalled frame (a dragged window, a breakpoint)
+/// doesn't make the animation jump
+pub const MAX_TICK: Duration = Duration::from_millis(250);
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct ClockOptions {
+    /// Animation time to start at in seconds, or of the frame rendered with --headless
+    /// and the first one with --record
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub time: f32,
+
+    /// Animation speed, negative plays backwards
+    #[arg(long, default_value_t = 1.0, allow_negative_numbers = true, value_parser = parse_speed, conflicts_with = "offscreen")]
+    pub speed: f64,
+
+    /// Start with the animation paused
+    #[arg(long, conflicts_with = "offscreen")]
+    pub paused: bool,
+
+    /// Advance the animation by this many seconds every frame instead of by the time that passed
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timestep, conflicts_with = "offscreen")]
+    pub timestep: Option<f64>,
+}
+
+impl Default for ClockOptions {
+    fn default() -> Self {
+        ClockOptions {
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+            timestep: None,
+        }
+    }
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !speed.is_finite() {
+        return Err("speed must be a finite number".into());
+    }
+    Ok(speed)
+}
+
+fn parse_timestep(s: &str) -> Result<f64, String> {
+    let step: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !step.is_finite() || step <= 0.0 {
+        return Err("timestep must be a positive number".into());
+    }
+    Ok(step)
+}
+
+/// Time fed to `Uniforms`, advanced once per frame by `tick`
+#[derive(Clone, Debug)]
+pub struct Clock {
+    /// Kept in f64 so long sessions don't lose precision before the conversion for the shader
+    time: f64,
+    start: f64,
+    speed: f64,
+    paused: bool,
+    timestep: Option<f64>,
+    last_tick: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new(options: &ClockOptions) -> Self {
+        Clock {
+            time: options.time as f64,
+            start: options.time as f64,
+            speed: options.speed,
+            paused: options.paused,
+            timestep: options.timestep,
+            last_tick: None,
+        }
+    }
+
+    /// Animation time in seconds
+    pub fn time(&self) -> f32 {
+        self.time as f32
+    }
+
+    /// Advances by the wall time since the previous tick, or by the fixed timestep, scaled by
+    /// the speed. Returns the time of the frame being drawn at `now`
+    pub fn tick(&mut self, now: Instant) -> f32 {
+        let elapsed = match self.last_tick {
+            Some(last) => now.saturating_duration_since(last).min(MAX_TICK),
+            None => Duration::ZERO,
+        };
+        let first = self.last_tick.is_none();
+        self.last_tick = Some(now);
+
+        if !self.paused && !first {
+            let delta = self.timestep.unwrap_or(elapsed.as_secs_f64());
+            self.time += delta * self.speed;
+        }
+        self.time()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Returns whether the clock is paused now
+    pub fn toggle_pause(&mut self) -> bool {
+        self.paused = !self.paused;
+        self.paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn seek(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    pub fn seek_by(&mut self, delta: f64) {
+        self.time += delta;
+    }
+
+    /// Back to the time the clock started at
+    pub fn restart(&mut self) {
+        self.time = self.start;
+    }
+
+    /// Time one frame advances by with `step`
+    pub fn step_size(&self) -> f64 {
+        self.timestep.unwrap_or(DEFAULT_STEP)
+    }
+
+    /// Pauses and moves `frames` frames forward, or backward when negative. Ignores the speed
+    pub fn step(&mut self, frames: i32) {
+        self.paused = true;
+        self.time += frames as f64 * self.step_size();
+    }
+}
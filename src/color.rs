@@ -0,0 +1,86 @@
+//! Color management: palettes are written in sRGB, the GPU works in linear light and the
+//! output is encoded back to sRGB, by the render target format when possible and by the
+//! tonemapping shader otherwise
+
+/// Decodes an sRGB encoded channel into linear light
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel to sRGB, the inverse of `srgb_to_linear`
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// sRGB encoded color, as parsed from the command line or generated by the palette
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Srgb(pub [f32; 3]);
+
+/// Color in linear light, as the shaders expect it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearRgb(pub [f32; 3]);
+
+impl Srgb {
+    pub fn to_linear(self) -> LinearRgb {
+        LinearRgb(self.0.map(srgb_to_linear))
+    }
+}
+
+impl LinearRgb {
+    pub fn to_srgb(self) -> Srgb {
+        Srgb(self.0.map(linear_to_srgb))
+    }
+}
+
+/// Format a render output is created with and the one it is drawn through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub format: wgpu::TextureFormat,
+    /// Differs from `format` when an sRGB view of a linear format is used
+    pub view_format: wgpu::TextureFormat,
+}
+
+impl OutputFormat {
+    /// Output drawn through an sRGB view of `format` when `srgb_views` are supported
+    pub fn new(format: wgpu::TextureFormat, srgb_views: bool) -> Self {
+        let view_format = if srgb_views { format.add_srgb_suffix() } else { format };
+        OutputFormat { format, view_format }
+    }
+
+    /// For the `view_formats` of the texture or surface configuration
+    pub fn view_formats(&self) -> Vec<wgpu::TextureFormat> {
+        if self.view_format == self.format { vec![] } else { vec![self.view_format] }
+    }
+
+    /// Whether writes get sRGB encoded by the hardware
+    pub fn is_srgb(&self) -> bool {
+        self.view_format.is_srgb()
+    }
+
+    pub fn create_view(&self, texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.view_format),
+            ..Default::default()
+        })
+    }
+}
+
+/// Picks a surface format that is encoded to sRGB by the hardware: an sRGB format if the surface
+/// offers one, an sRGB view of the preferred format where views are supported, or the preferred
+/// format itself, leaving the encoding to the tonemapping shader
+pub fn surface_format(caps: &wgpu::SurfaceCapabilities, downlevel: wgpu::DownlevelFlags) -> OutputFormat {
+    if let Some(&format) = caps.formats.iter().find(|f| f.is_srgb()) {
+        return OutputFormat::new(format, false);
+    }
+
+    let format = caps.formats[0];
+    OutputFormat::new(format, downlevel.contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS))
+}
@@ -0,0 +1,107 @@
+use clap::{CommandFactory, Parser};
+
+use std::path::PathBuf;
+
+use crate::{
+    adapter::AdapterOptions, clock::ClockOptions, hot_reload::DEFAULT_SHADER_DIR, pacing::PacingOptions, palette::PaletteOptions,
+    pipeline_cache::DEFAULT_PIPELINE_CACHE_DIR, record::RecordOptions, renderer::RenderOptions, text::TextMode,
+};
+
+/// Animated noise background with warped text
+#[derive(Parser, Clone, Debug)]
+#[command(version, about)]
+#[command(group(clap::ArgGroup::new("offscreen").args(["headless", "record"])))]
+pub struct Config {
+    #[command(flatten)]
+    pub render: RenderOptions,
+
+    #[command(flatten)]
+    pub palette: PaletteOptions,
+
+    /// Window title
+    #[arg(long, default_value = "Sus window")]
+    pub title: String,
+
+    /// Initial window size, or the output resolution with --headless and --record
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+
+    /// Start in borderless fullscreen
+    #[arg(long, conflicts_with = "offscreen")]
+    pub fullscreen: bool,
+
+    #[command(flatten)]
+    pub adapter: AdapterOptions,
+
+    #[command(flatten)]
+    pub pacing: PacingOptions,
+
+    /// Show the performance overlay, F3 toggles it
+    #[arg(long, conflicts_with = "offscreen")]
+    pub overlay: bool,
+
+    /// Load the WGSL files from DIR (the source tree by default) and rebuild
+    /// the pipelines whenever they change
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = DEFAULT_SHADER_DIR, conflicts_with = "offscreen")]
+    pub watch_shaders: Option<PathBuf>,
+
+    /// Keep compiled pipelines in DIR (target/pipeline-cache by default) between runs,
+    /// on backends that support it
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = DEFAULT_PIPELINE_CACHE_DIR, env = "WGPU_TEST_PIPELINE_CACHE")]
+    pub pipeline_cache: Option<PathBuf>,
+
+    /// Render a single frame to this PNG file instead of opening a window
+    #[arg(long, value_name = "OUT.png")]
+    pub headless: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub record: RecordOptions,
+
+    #[command(flatten)]
+    pub clock: ClockOptions,
+}
+
+pub const DEFAULT_HEADLESS_SIZE: (u32, u32) = (1280, 720);
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s.split_once('x').ok_or("size must be in <W>x<H> format")?;
+    let w: u32 = w.parse().map_err(|e| format!("invalid width: {e}"))?;
+    let h: u32 = h.parse().map_err(|e| format!("invalid height: {e}"))?;
+
+    if w == 0 || h == 0 {
+        return Err("width and height must be greater than 0".into());
+    }
+    Ok((w, h))
+}
+
+impl Config {
+    /// Parses the command line, printing help or a validation error and exiting when needed
+    pub fn from_args() -> Self {
+        let config = Config::parse();
+
+        if let Err(e) = config.validate() {
+            Config::command()
+                .error(clap::error::ErrorKind::ValueValidation, e)
+                .exit();
+        }
+
+        config
+    }
+
+    /// Checks that can't be expressed through individual argument parsers
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(headless) = &self.headless {
+            let is_png = headless.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+            if !is_png {
+                return Err(format!("headless output {} must be a .png file", headless.display()));
+            }
+        }
+
+        let text = &self.render.text;
+        if text.text_mode != TextMode::Sdf && text.sdf.has_effects() {
+            return Err("text outline, shadow and glow need --text-mode sdf".into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use ab_glyph::{Font, FontVec, GlyphId};
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Tried after the user supplied fonts, in order. Missing ones are skipped
+pub const FALLBACK_FAMILIES: &[&str] = &[
+    "IosevkaTerm Nerd Font",
+    "Iosevka Term",
+    "DejaVu Sans Mono",
+    "Noto Sans Mono",
+    "Liberation Mono",
+    "Menlo",
+    "Consolas",
+    "DejaVu Sans",
+    "Noto Sans",
+    "Arial",
+    // Wider script and symbol coverage
+    "Noto Sans CJK SC",
+    "Noto Sans CJK JP",
+    "Source Han Sans",
+    "Microsoft YaHei",
+    "PingFang SC",
+    "Noto Emoji",
+    "Symbola",
+    "Segoe UI Emoji",
+];
+
+/// Fonts tried in order for every character, so glyphs missing in the
+/// primary font (e.g. Cyrillic, CJK, emoji) are taken from the next one that has them.
+/// Fallback faces are only read the first time a glyph is looked up in them
+pub struct FontChain {
+    db: fontdb::Database,
+    fonts: Vec<ChainFont>,
+}
+
+struct ChainFont {
+    name: String,
+    /// Face in the database for fonts loaded on first use
+    face: Option<fontdb::ID>,
+    font: OnceLock<Option<FontVec>>,
+}
+
+impl ChainFont {
+    fn loaded(name: String, font: FontVec) -> Self {
+        ChainFont { name, face: None, font: OnceLock::from(Some(font)) }
+    }
+
+    fn get(&self, db: &fontdb::Database) -> Option<&FontVec> {
+        self.font.get_or_init(|| {
+            let font = self.face.and_then(|id| load_face(db, id));
+            match &font {
+                Some(_) => log::debug!("Loaded fallback font {:?}", self.name),
+                None => log::warn!("Unable to load fallback font {:?}, skipping it", self.name),
+            }
+            font
+        }).as_ref()
+    }
+}
+
+fn load_face(db: &fontdb::Database, id: fontdb::ID) -> Option<FontVec> {
+    db.with_face_data(id, |data, index| FontVec::try_from_vec_and_index(data.to_vec(), index).ok())
+        .flatten()
+}
+
+fn query_family(db: &fontdb::Database, family: &str) -> Option<fontdb::ID> {
+    db.query(&fontdb::Query {
+        families: &[fontdb::Family::Name(family)],
+        weight: fontdb::Weight::BOLD,
+        ..Default::default()
+    })
+}
+
+/// File and index of a face, so families resolving to the same face are only loaded once
+fn face_file(db: &fontdb::Database, id: fontdb::ID) -> Option<(PathBuf, u32)> {
+    let face = db.face(id)?;
+    match &face.source {
+        fontdb::Source::File(path) | fontdb::Source::SharedFile(path, _) => Some((canonical(path), face.index)),
+        fontdb::Source::Binary(_) => None,
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+impl FontChain {
+    /// Each entry of `fonts` is either a path to a font file or a family name of an installed font.
+    /// `FALLBACK_FAMILIES` are appended after them, skipping faces already in the chain
+    pub fn load(fonts: &[String]) -> anyhow::Result<Self> {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        log::debug!("Found {} system font faces", db.len());
+
+        let mut chain = vec![];
+        let mut files = HashSet::new();
+
+        for font in fonts {
+            let path = Path::new(font);
+
+            if path.is_file() {
+                let data = std::fs::read(path)
+                    .map_err(|e| anyhow::anyhow!("Unable to read font {}: {e}", path.display()))?;
+                let loaded = FontVec::try_from_vec(data)
+                    .map_err(|e| anyhow::anyhow!("Unable to parse font {}: {e}", path.display()))?;
+                files.insert((canonical(path), 0));
+                chain.push(ChainFont::loaded(font.clone(), loaded));
+                continue;
+            }
+
+            let loaded = query_family(&db, font).and_then(|id| {
+                files.extend(face_file(&db, id));
+                load_face(&db, id)
+            });
+            match loaded {
+                Some(f) => chain.push(ChainFont::loaded(font.clone(), f)),
+                None => anyhow::bail!("Font {font:?} is neither a font file nor an installed font family"),
+            }
+        }
+
+        for family in FALLBACK_FAMILIES {
+            let Some(id) = query_family(&db, family) else {
+                log::debug!("Fallback font {family:?} is not installed");
+                continue;
+            };
+
+            if face_file(&db, id).is_some_and(|file| !files.insert(file)) {
+                log::debug!("Fallback font {family:?} is already in the chain");
+                continue;
+            }
+            chain.push(ChainFont { name: family.to_string(), face: Some(id), font: OnceLock::new() });
+        }
+
+        // The primary font is drawn for missing glyphs, so it has to be loaded
+        while chain.first().is_some_and(|font| font.get(&db).is_none()) {
+            chain.remove(0);
+        }
+
+        anyhow::ensure!(
+            !chain.is_empty(),
+            "No usable font found. Install one of {FALLBACK_FAMILIES:?} or pass a font file with --font",
+        );
+
+        let chain = FontChain { db, fonts: chain };
+        log::info!("Font chain: {:?}", chain.names().collect::<Vec<_>>());
+        Ok(chain)
+    }
+
+    /// First font in the chain that has a glyph for `c`, falling back to the
+    /// primary font's missing glyph box
+    pub fn glyph(&self, c: char) -> (usize, GlyphId) {
+        self.fonts.iter()
+            .enumerate()
+            .filter_map(|(i, font)| Some((i, font.get(&self.db)?.glyph_id(c))))
+            .find(|(_, id)| id.0 != 0)
+            .unwrap_or((0, GlyphId(0)))
+    }
+
+    /// Font at `index`, as returned by `glyph`
+    pub fn font(&self, index: usize) -> &FontVec {
+        self.fonts[index].get(&self.db).expect("font returned by glyph is loaded")
+    }
+
+    /// Names of the fonts in the chain, including fallbacks not loaded yet
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.fonts.iter().map(|font| font.name.as_str())
+    }
+
+    /// Number of fonts read so far
+    pub fn loaded(&self) -> usize {
+        self.fonts.iter().filter(|font| font.font.get().is_some_and(Option::is_some)).count()
+    }
+}
@@ -0,0 +1,391 @@
+//! Render graph: passes declare the targets they read and write, the graph allocates
+//! the intermediate targets, keeps them sized relative to the output and records
+//! the passes in dependency order
+
+use std::{any::Any, time::Duration};
+
+use crate::{
+    hot_reload::Shader,
+    renderer::{get_render_target, PipelineError},
+    timing::GpuTimer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+/// Where a pass draws to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attachment {
+    Target(TargetId),
+    /// View passed to `RenderGraph::execute`, e.g. the window surface
+    Output,
+}
+
+/// Intermediate render target, sampleable through `texture_bind_group_layout`
+#[derive(Clone, Debug)]
+pub struct TargetDesc {
+    pub label: String,
+    pub format: wgpu::TextureFormat,
+    /// Size relative to the output, 0.5 is half the width and height
+    pub scale: f32,
+    /// Above 1, passes draw into a multisampled texture that is resolved into the target
+    pub sample_count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PassDesc {
+    pub label: String,
+    /// Targets the pass samples, their bind groups are handed to the pass in this order
+    pub inputs: Vec<TargetId>,
+    pub output: Attachment,
+    /// `None` keeps what earlier passes drew into the output
+    pub clear: Option<wgpu::Color>,
+}
+
+/// Everything a pass needs to record itself
+pub struct PassContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub output: &'a wgpu::TextureView,
+    /// Where `output` gets resolved to when it is multisampled
+    pub resolve_target: Option<&'a wgpu::TextureView>,
+    pub output_size: (u32, u32),
+    /// Bind groups of `PassDesc::inputs`
+    pub inputs: &'a [&'a wgpu::BindGroup],
+    desc: &'a PassDesc,
+}
+
+impl PassContext<'_> {
+    /// Starts a render pass into the output, cleared or loaded as the pass was declared
+    pub fn begin_render_pass(&mut self) -> wgpu::RenderPass<'_> {
+        let load = match self.desc.clear {
+            Some(color) => wgpu::LoadOp::Clear(color),
+            None => wgpu::LoadOp::Load,
+        };
+
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&self.desc.label),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: self.output,
+                    resolve_target: self.resolve_target,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+}
+
+pub trait Pass: Any {
+    /// Called every frame before any pass is recorded, with the size of the graph output
+    fn prepare(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _output_size: (u32, u32)) {}
+
+    fn record(&self, ctx: &mut PassContext);
+
+    /// Rebuilds the pipeline that uses `shader`, if any. On error the previous pipeline is kept
+    fn reload_shader(&mut self, _device: &wgpu::Device, _shader: Shader, _source: &str) -> Result<(), PipelineError> {
+        Ok(())
+    }
+}
+
+struct Target {
+    desc: TargetDesc,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    /// Drawn into instead of `texture` when `desc.sample_count` is above 1
+    multisampled: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl Target {
+    fn new(device: &wgpu::Device, desc: TargetDesc, output_size: (u32, u32)) -> Self {
+        let size = scaled_size(output_size, desc.scale);
+        let (texture, bind_group) = get_render_target(device, &desc.label, desc.format, size);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let multisampled = (desc.sample_count > 1).then(|| {
+            let texture = get_multisampled_texture(device, &desc, size);
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        });
+
+        Target { desc, texture, view, bind_group, multisampled }
+    }
+
+    fn destroy(&self) {
+        self.texture.destroy();
+        if let Some((texture, _)) = &self.multisampled {
+            texture.destroy();
+        }
+    }
+
+    /// View passes draw into and the one it is resolved to, if any
+    fn attachment(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.multisampled {
+            Some((_, view)) => (view, Some(&self.view)),
+            None => (&self.view, None),
+        }
+    }
+}
+
+fn get_multisampled_texture(device: &wgpu::Device, desc: &TargetDesc, size: (u32, u32)) -> wgpu::Texture {
+    device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some(&format!("{}_multisampled", desc.label)),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: desc.sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: desc.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn scaled_size(size: (u32, u32), scale: f32) -> (u32, u32) {
+    let scale = |v: u32| ((v as f32 * scale).round() as u32).max(1);
+    (scale(size.0), scale(size.1))
+}
+
+/// Passes and the targets between them, see the module docs
+pub struct RenderGraph {
+    targets: Vec<Target>,
+    passes: Vec<(PassDesc, Box<dyn Pass>)>,
+    /// Indices into `passes`, in execution order
+    order: Vec<usize>,
+    size: (u32, u32),
+    timing: bool,
+    /// Marker before the first pass and after every pass, created by `prepare` while `timing` is set
+    timer: Option<GpuTimer>,
+}
+
+impl RenderGraph {
+    pub fn new(size: (u32, u32)) -> Self {
+        RenderGraph {
+            targets: vec![],
+            passes: vec![],
+            order: vec![],
+            size,
+            timing: false,
+            timer: None,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn add_target(&mut self, device: &wgpu::Device, desc: TargetDesc) -> TargetId {
+        self.targets.push(Target::new(device, desc, self.size));
+        TargetId(self.targets.len() - 1)
+    }
+
+    pub fn target_format(&self, id: TargetId) -> wgpu::TextureFormat {
+        self.targets[id.0].desc.format
+    }
+
+    pub fn target_texture(&self, id: TargetId) -> &wgpu::Texture {
+        &self.targets[id.0].texture
+    }
+
+    /// Adds a pass and reorders the graph. Fails, leaving the graph unchanged,
+    /// if the pass reads its own output or creates a dependency cycle
+    pub fn add_pass(&mut self, desc: PassDesc, pass: impl Pass) -> anyhow::Result<PassId> {
+        if let Attachment::Target(output) = desc.output {
+            anyhow::ensure!(!desc.inputs.contains(&output), "Pass {:?} reads the target it draws to", desc.label);
+        }
+
+        self.passes.push((desc, Box::new(pass)));
+
+        match self.sort() {
+            Ok(order) => {
+                self.order = order;
+                Ok(PassId(self.passes.len() - 1))
+            },
+            Err(e) => {
+                self.passes.pop();
+                Err(e)
+            },
+        }
+    }
+
+    pub fn pass<T: Pass>(&self, id: PassId) -> Option<&T> {
+        let pass: &dyn Any = self.passes.get(id.0)?.1.as_ref();
+        pass.downcast_ref()
+    }
+
+    pub fn pass_mut<T: Pass>(&mut self, id: PassId) -> Option<&mut T> {
+        let pass: &mut dyn Any = self.passes.get_mut(id.0)?.1.as_mut();
+        pass.downcast_mut()
+    }
+
+    /// Labels of the passes in execution order
+    pub fn pass_order(&self) -> Vec<&str> {
+        self.order.iter().map(|&i| self.passes[i].0.label.as_str()).collect()
+    }
+
+    /// Orders passes so every target is fully drawn before anything samples it.
+    /// Passes drawing into the same target keep the order they were added in
+    fn sort(&self) -> anyhow::Result<Vec<usize>> {
+        let n = self.passes.len();
+        let mut edges = vec![vec![]; n];
+
+        let mut last_writer: Vec<Option<usize>> = vec![None; self.targets.len()];
+        let mut last_output_writer = None;
+
+        for (i, (desc, _)) in self.passes.iter().enumerate() {
+            let previous = match desc.output {
+                Attachment::Target(t) => last_writer[t.0].replace(i),
+                Attachment::Output => last_output_writer.replace(i),
+            };
+            if let Some(previous) = previous {
+                edges[previous].push(i);
+            }
+        }
+
+        for (reader, (desc, _)) in self.passes.iter().enumerate() {
+            for input in &desc.inputs {
+                let writers = self.passes.iter()
+                    .enumerate()
+                    .filter(|(_, (d, _))| d.output == Attachment::Target(*input))
+                    .map(|(i, _)| i);
+
+                for writer in writers {
+                    edges[writer].push(reader);
+                }
+            }
+        }
+
+        // Kahn's algorithm, preferring passes added earlier
+        let mut in_degree = vec![0; n];
+        for to in edges.iter().flatten() {
+            in_degree[*to] += 1;
+        }
+
+        let mut order = Vec::with_capacity(n);
+        let mut done = vec![false; n];
+        while order.len() < n {
+            let Some(next) = (0..n).find(|&i| !done[i] && in_degree[i] == 0) else {
+                let stuck: Vec<&str> = (0..n).filter(|&i| !done[i]).map(|i| self.passes[i].0.label.as_str()).collect();
+                anyhow::bail!("Render graph has a dependency cycle between passes {stuck:?}");
+            };
+
+            done[next] = true;
+            order.push(next);
+            for to in &edges[next] {
+                in_degree[*to] -= 1;
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Recreates every target for the new output size
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if size.0 == 0 || size.1 == 0 || size == self.size {
+            return;
+        }
+
+        self.size = size;
+        for target in &mut self.targets {
+            target.destroy();
+            *target = Target::new(device, target.desc.clone(), size);
+        }
+    }
+
+    /// Tries `source` on every pass, returning the first error
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        let mut result = Ok(());
+        for (_, pass) in &mut self.passes {
+            let reloaded = pass.reload_shader(device, shader, source);
+            if result.is_ok() {
+                result = reloaded;
+            }
+        }
+        result
+    }
+
+    /// Measures the GPU time of every pass from the next frame on, when the device
+    /// has `Features::TIMESTAMP_QUERY`
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timing = enabled;
+        if !enabled {
+            self.timer = None;
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.timing {
+            let markers = self.passes.len() as u32 + 1;
+            if self.timer.as_ref().is_none_or(|timer| timer.markers() != markers) {
+                self.timer = GpuTimer::new(device, queue, markers);
+            }
+            if let Some(timer) = &mut self.timer {
+                timer.begin_frame();
+            }
+        }
+
+        for (_, pass) in &mut self.passes {
+            pass.prepare(device, queue, self.size);
+        }
+    }
+
+    /// Call once the encoder given to `execute` is submitted, to read the pass timings back
+    pub fn frame_submitted(&mut self) {
+        if let Some(timer) = &mut self.timer {
+            timer.frame_submitted();
+        }
+    }
+
+    /// GPU time of each pass in execution order, from the newest measured frame.
+    /// Lags a few frames behind `execute`
+    pub fn pass_timings(&self) -> Option<Vec<(&str, Duration)>> {
+        let durations = self.timer.as_ref()?.durations()?;
+        Some(self.pass_order().into_iter().zip(durations.iter().copied()).collect())
+    }
+
+    /// Records every pass into `encoder`, `output` being the view behind `Attachment::Output`
+    pub fn execute(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        if let Some(timer) = &self.timer {
+            timer.write_marker(encoder, 0);
+        }
+
+        for (marker, &i) in (1..).zip(&self.order) {
+            let (desc, pass) = &self.passes[i];
+
+            let inputs: Vec<&wgpu::BindGroup> = desc.inputs.iter().map(|t| &self.targets[t.0].bind_group).collect();
+            let ((view, resolve_target), output_size) = match desc.output {
+                Attachment::Target(t) => (self.targets[t.0].attachment(), scaled_size(self.size, self.targets[t.0].desc.scale)),
+                Attachment::Output => ((output, None), self.size),
+            };
+
+            pass.record(&mut PassContext {
+                encoder,
+                output: view,
+                resolve_target,
+                output_size,
+                inputs: &inputs,
+                desc,
+            });
+
+            if let Some(timer) = &self.timer {
+                timer.write_marker(encoder, marker);
+            }
+        }
+
+        if let Some(timer) = &self.timer {
+            timer.resolve(encoder);
+        }
+    }
+}
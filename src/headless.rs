@@ -0,0 +1,309 @@
+use imageproc::image::RgbaImage;
+
+use std::{path::Path, sync::mpsc};
+
+use crate::{
+    adapter::AdapterOptions,
+    pipeline_cache::DiskPipelineCache,
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Format of the `Headless` target, sRGB encoded by the hardware like most window surfaces
+pub const HEADLESS_SRGB_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Renders frames into an offscreen texture instead of a window surface
+pub struct Headless {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    renderer: Renderer,
+    target: wgpu::Texture,
+    pipeline_cache: Option<DiskPipelineCache>,
+}
+
+/// Offscreen `HEADLESS_FORMAT` render target that can be read back with `read_texture`
+pub fn get_target_texture(device: &wgpu::Device, size: (u32, u32)) -> wgpu::Texture {
+    get_target_texture_with_format(device, size, HEADLESS_FORMAT)
+}
+
+/// Like `get_target_texture`, `format` having 4 bytes per pixel
+pub fn get_target_texture_with_format(device: &wgpu::Device, size: (u32, u32), format: wgpu::TextureFormat) -> wgpu::Texture {
+    device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some("headless_target"),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Picks an adapter without a surface, so this works without a display.
+/// `WGPU_BACKEND` and `WGPU_ADAPTER_NAME` can be used to force a specific adapter,
+/// `force_fallback_adapter` restricts the choice to software adapters
+pub async fn request_adapter(force_fallback_adapter: bool) -> anyhow::Result<wgpu::Adapter> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+
+    let adapter = if force_fallback_adapter {
+        instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        }).await?
+    } else {
+        wgpu::util::initialize_adapter_from_env_or_default(&instance, None).await?
+    };
+
+    Ok(adapter)
+}
+
+/// Env var that lets the tests needing a software adapter pass without one
+pub const ALLOW_NO_ADAPTER_ENV: &str = "WGPU_TEST_ALLOW_NO_ADAPTER";
+
+/// For tests that couldn't get an adapter: skips them when `ALLOW_NO_ADAPTER_ENV` is set and fails
+/// them otherwise, so a machine without an adapter doesn't report the GPU tests as passing
+pub fn skip_without_adapter(test: &str, error: impl std::fmt::Display) {
+    assert!(
+        std::env::var_os(ALLOW_NO_ADAPTER_ENV).is_some(),
+        "No fallback adapter for the {test}: {error}. Set {ALLOW_NO_ADAPTER_ENV}=1 to skip it",
+    );
+    eprintln!("Skipping {test}, no fallback adapter available: {error}");
+}
+
+/// Opens the adapter picked by `request_adapter`
+pub async fn request_device(force_fallback_adapter: bool) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+    request_device_from(&request_adapter(force_fallback_adapter).await?).await
+}
+
+/// Features the renderer makes use of when the adapter has them
+pub fn optional_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+    adapter.features()
+        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES | wgpu::Features::PIPELINE_CACHE | wgpu::Features::TIMESTAMP_QUERY)
+}
+
+/// Opens `adapter` with the limits every supported backend provides
+pub async fn request_device_from(adapter: &wgpu::Adapter) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+    let adapter_info = adapter.get_info();
+    log::info!("Using adapter: {}, Backend: {:?}", adapter_info.name, adapter_info.backend);
+
+    let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
+        label: None,
+        required_features: optional_features(adapter),
+        required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+        memory_hints: Default::default(),
+        trace: wgpu::Trace::Off,
+    }).await?;
+
+    Ok((device, queue))
+}
+
+/// Copies a `Rgba8Unorm` or `Rgba8UnormSrgb` texture with `COPY_SRC` usage back to the CPU.
+/// Any pending work on `queue` is waited on
+pub fn read_texture(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> anyhow::Result<RgbaImage> {
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Readback encoder") });
+    let readback = ReadbackBuffer::new(device, &mut encoder, texture);
+    queue.submit(std::iter::once(encoder.finish()));
+    readback.read(device)
+}
+
+/// Staging buffer receiving a copy of a texture
+struct ReadbackBuffer {
+    buffer: wgpu::Buffer,
+    size: (u32, u32),
+    padded_bytes_per_row: u32,
+}
+
+impl ReadbackBuffer {
+    fn new(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) -> Self {
+        Self::reusing(device, encoder, texture, None)
+    }
+
+    /// Copies into `buffer` when it is large enough, instead of allocating a new one
+    fn reusing(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, buffer: Option<wgpu::Buffer>) -> Self {
+        let size = (texture.width(), texture.height());
+
+        // Rows in a texture to buffer copy must be aligned to 256 bytes
+        let padded_bytes_per_row = (4 * size.0).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer_size = (padded_bytes_per_row * size.1) as wgpu::BufferAddress;
+
+        let buffer = match buffer {
+            Some(buffer) if buffer.size() >= buffer_size => buffer,
+            _ => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Readback buffer"),
+                size: buffer_size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        };
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.1),
+                },
+            },
+            texture.size(),
+        );
+
+        ReadbackBuffer { buffer, size, padded_bytes_per_row }
+    }
+
+    /// Starts mapping the buffer, which completes once the copy is done and the device is polled
+    fn map(&self) -> mpsc::Receiver<Result<(), wgpu::BufferAsyncError>> {
+        let (tx, rx) = mpsc::channel();
+        self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| { let _ = tx.send(res); });
+        rx
+    }
+
+    fn read(self, device: &wgpu::Device) -> anyhow::Result<RgbaImage> {
+        let mapped = self.map();
+        device.poll(wgpu::PollType::Wait)?;
+        mapped.recv()??;
+
+        Ok(self.read_mapped()?.0)
+    }
+
+    /// Copies the image out of the mapped buffer and unmaps it for the next use
+    fn read_mapped(self) -> anyhow::Result<(RgbaImage, wgpu::Buffer)> {
+        let unpadded_bytes_per_row = 4 * self.size.0;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.size.1) as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks_exact(self.padded_bytes_per_row as usize).take(self.size.1 as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        self.buffer.unmap();
+
+        let img = RgbaImage::from_raw(self.size.0, self.size.1, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Readback buffer size mismatch"))?;
+        Ok((img, self.buffer))
+    }
+}
+
+/// Frame on its way back from the GPU, see `Headless::render_async`
+pub struct PendingFrame {
+    readback: ReadbackBuffer,
+    submission: wgpu::SubmissionIndex,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl PendingFrame {
+    /// Waits for the copy to finish. Returns the frame and its staging buffer, which can be
+    /// handed to the next `render_async`
+    pub fn wait(self, device: &wgpu::Device) -> anyhow::Result<(RgbaImage, wgpu::Buffer)> {
+        device.poll(wgpu::PollType::WaitForSubmissionIndex(self.submission))?;
+        self.mapped.recv()??;
+        self.readback.read_mapped()
+    }
+}
+
+impl Headless {
+    /// Renders with the adapter `adapter` selects, compiling the pipelines through the on-disk cache
+    /// in `pipeline_cache_dir`, when given and supported
+    pub async fn new(
+        size: (u32, u32),
+        colors: Colors,
+        options: &RenderOptions,
+        adapter: &AdapterOptions,
+        pipeline_cache_dir: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let adapter = adapter.select(&adapter.instance(), None)?;
+        let (device, queue) = request_device_from(&adapter).await?;
+
+        let pipeline_cache = pipeline_cache_dir.and_then(|dir| DiskPipelineCache::load(&device, &adapter.get_info(), dir));
+        let mut options = options.clone().with_adapter(&adapter);
+        options.pipeline_cache = pipeline_cache.as_ref().map(|c| c.cache().clone());
+
+        let mut headless = Self::with_device(device, queue, size, colors, &options)?;
+        headless.pipeline_cache = pipeline_cache;
+        Ok(headless)
+    }
+
+    /// Renders with an already opened device, e.g. one of a specific backend
+    pub fn with_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        size: (u32, u32),
+        colors: Colors,
+        options: &RenderOptions,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(size.0 > 0 && size.1 > 0, "Invalid render size {}x{}", size.0, size.1);
+
+        let renderer = Renderer::new(&device, HEADLESS_SRGB_FORMAT, size, colors, options)?;
+        let target = get_target_texture_with_format(&device, size, HEADLESS_SRGB_FORMAT);
+
+        Ok(Headless { device, queue, renderer, target, pipeline_cache: None })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.renderer.size()
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    /// Writes the pipeline cache back to disk, if `new` opened one
+    pub fn save_pipeline_cache(&self) -> anyhow::Result<()> {
+        match &self.pipeline_cache {
+            Some(cache) => cache.save(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn on_resize(&mut self, w: u32, h: u32) {
+        if w == 0 || h == 0 || (w, h) == self.size() {
+            return;
+        }
+
+        self.renderer.on_resize(&self.device, w, h);
+        self.target.destroy();
+        self.target = get_target_texture_with_format(&self.device, (w, h), HEADLESS_SRGB_FORMAT);
+    }
+
+    /// Renders a single frame at `time` seconds and reads it back from the GPU
+    pub fn render(&mut self, time: f32) -> anyhow::Result<RgbaImage> {
+        let view = self.target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Headless encoder") });
+
+        self.renderer.render(&self.device, &self.queue, &mut encoder, &view, time);
+        let readback = ReadbackBuffer::new(&self.device, &mut encoder, &self.target);
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        readback.read(&self.device)
+    }
+
+    /// Renders a single frame at `time` seconds and starts copying it back, without waiting for it.
+    /// `staging` is the buffer of an earlier `PendingFrame` to copy into instead of allocating one
+    pub fn render_async(&mut self, time: f32, staging: Option<wgpu::Buffer>) -> PendingFrame {
+        let view = self.target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Headless encoder") });
+
+        self.renderer.render(&self.device, &self.queue, &mut encoder, &view, time);
+        let readback = ReadbackBuffer::reusing(&self.device, &mut encoder, &self.target, staging);
+
+        let submission = self.queue.submit(std::iter::once(encoder.finish()));
+        let mapped = readback.map();
+        PendingFrame { readback, submission, mapped }
+    }
+
+    pub fn render_to_png(&mut self, time: f32, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let img = self.render(time)?;
+        img.save(path.as_ref())?;
+        log::info!("Saved frame at {time}s to {}", path.as_ref().display());
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Dev mode that watches the WGSL files on disk and rebuilds their pipelines when they change
+
+use notify::Watcher;
+
+use std::{ffi::OsStr, path::PathBuf, sync::mpsc};
+
+use crate::renderer::{
+    BLOOM_SHADER, NOISE_LIB_SHADER, NOISE_SHADER, POST_SHADER, SCENE_SHADER, TEXT_SDF_SHADER, TEXT_SHADER, TONEMAP_SHADER,
+};
+
+/// Shader directory of the source tree, watched by default
+pub const DEFAULT_SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Shader {
+    Scene,
+    Text,
+    TextSdf,
+    Post,
+    Bloom,
+    Tonemap,
+    Noise,
+    NoiseLib,
+}
+
+impl Shader {
+    pub const ALL: [Shader; 8] = [
+        Shader::Scene, Shader::Text, Shader::TextSdf, Shader::Post, Shader::Bloom, Shader::Tonemap, Shader::Noise, Shader::NoiseLib,
+    ];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Shader::Scene => "shader.wgsl",
+            Shader::Text => "shader_text.wgsl",
+            Shader::TextSdf => "shader_text_sdf.wgsl",
+            Shader::Post => "shader_post.wgsl",
+            Shader::Bloom => "shader_bloom.wgsl",
+            Shader::Tonemap => "shader_tonemap.wgsl",
+            Shader::Noise => "shader_noise.wgsl",
+            Shader::NoiseLib => "shader_noise_lib.wgsl",
+        }
+    }
+
+    /// Source baked into the binary
+    pub fn builtin(self) -> &'static str {
+        match self {
+            Shader::Scene => SCENE_SHADER,
+            Shader::Text => TEXT_SHADER,
+            Shader::TextSdf => TEXT_SDF_SHADER,
+            Shader::Post => POST_SHADER,
+            Shader::Bloom => BLOOM_SHADER,
+            Shader::Tonemap => TONEMAP_SHADER,
+            Shader::Noise => NOISE_SHADER,
+            Shader::NoiseLib => NOISE_LIB_SHADER,
+        }
+    }
+
+    /// Whether `NoiseLib` is appended to the source before it is compiled, see `noise_lib::with_noise_lib`
+    pub fn uses_noise_lib(self) -> bool {
+        matches!(self, Shader::Scene | Shader::Noise)
+    }
+
+    fn from_file_name(name: &OsStr) -> Option<Shader> {
+        Shader::ALL.into_iter().find(|s| name == s.file_name())
+    }
+}
+
+/// Watches a directory for changes of the files in `Shader::ALL`
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        anyhow::ensure!(dir.is_dir(), "Shader directory {} does not exist", dir.display());
+
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        // Editors often save by replacing the file, which would end a watch on the file itself
+        watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+        log::info!("Watching shaders in {}", dir.display());
+
+        Ok(ShaderWatcher { dir, events, _watcher: watcher })
+    }
+
+    pub fn path(&self, shader: Shader) -> PathBuf {
+        self.dir.join(shader.file_name())
+    }
+
+    /// Shaders whose files changed since the last call. Doesn't block
+    pub fn changed(&self) -> Vec<Shader> {
+        let mut changed = vec![];
+
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("Shader watcher error: {e}");
+                    continue;
+                },
+            };
+
+            if !(event.kind.is_create() || event.kind.is_modify()) {
+                continue;
+            }
+
+            let shaders = event.paths.iter().filter_map(|p| p.file_name().and_then(Shader::from_file_name));
+            for shader in shaders {
+                if !changed.contains(&shader) {
+                    changed.push(shader);
+                }
+            }
+        }
+
+        changed
+    }
+
+    pub fn load(&self, shader: Shader) -> anyhow::Result<String> {
+        let path = self.path(shader);
+        std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Unable to read {}: {e}", path.display()))
+    }
+}
@@ -0,0 +1,160 @@
+use ab_glyph::{Font, GlyphId, PxScale, ScaleFont};
+
+use crate::font::FontChain;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutOptions {
+    /// Lines are wrapped at word boundaries to fit this width in pixels.
+    /// Words longer than a line are broken between characters
+    pub max_width: Option<f32>,
+    pub align: Align,
+    /// Multiplier for the font's line height
+    pub line_spacing: f32,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            max_width: None,
+            align: Align::Center,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// Glyph with its pen position relative to the top left corner of the text block
+#[derive(Clone, Copy, Debug)]
+pub struct PositionedGlyph {
+    pub font_index: usize,
+    pub glyph: GlyphId,
+    /// Pen position on the baseline
+    pub pos: [f32; 2],
+    pub advance: f32,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    pub glyphs: Vec<PositionedGlyph>,
+    /// Size of the text block in pixels
+    pub size: [f32; 2],
+    pub line_count: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LineGlyph {
+    font_index: usize,
+    glyph: GlyphId,
+    x: f32,
+    advance: f32,
+    is_space: bool,
+}
+
+/// Width of a line without its trailing whitespace
+fn line_width(line: &[LineGlyph]) -> f32 {
+    line.iter()
+        .rev()
+        .find(|g| !g.is_space)
+        .map_or(0.0, |g| g.x + g.advance)
+}
+
+/// Breaks `text` into lines at newlines and, with `max_width`, at the last whitespace
+/// that keeps the line short enough. Every glyph is taken from the first font that has it
+fn break_lines(fonts: &FontChain, text: &str, scale: PxScale, max_width: Option<f32>) -> Vec<Vec<LineGlyph>> {
+    let mut lines = vec![];
+
+    for paragraph in text.split('\n') {
+        let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+
+        let mut line: Vec<LineGlyph> = vec![];
+        let mut x = 0.0;
+        let mut prev: Option<(usize, GlyphId)> = None;
+        // Index of the first glyph after the last run of whitespace
+        let mut break_at: Option<usize> = None;
+
+        for c in paragraph.chars().filter(|c| !c.is_control()) {
+            let (font_index, glyph) = fonts.glyph(c);
+            let font = fonts.font(font_index).as_scaled(scale);
+
+            if let Some((prev_index, prev_glyph)) = prev && prev_index == font_index {
+                x += font.kern(prev_glyph, glyph);
+            }
+
+            let advance = font.h_advance(glyph);
+            let is_space = c.is_whitespace();
+
+            if let Some(max_width) = max_width && !is_space && !line.is_empty() && x + advance > max_width {
+                let rest = line.split_off(break_at.unwrap_or(line.len()));
+                lines.push(line);
+
+                // Move the unfinished word to the start of the next line
+                let shift = rest.first().map_or(x, |g| g.x);
+                line = rest.into_iter().map(|g| LineGlyph { x: g.x - shift, ..g }).collect();
+                x -= shift;
+                break_at = None;
+            }
+
+            line.push(LineGlyph { font_index, glyph, x, advance, is_space });
+            x += advance;
+            prev = Some((font_index, glyph));
+
+            if is_space {
+                break_at = Some(line.len());
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+pub fn layout(fonts: &FontChain, text: &str, scale: PxScale, options: &LayoutOptions) -> Layout {
+    let lines = break_lines(fonts, text, scale, options.max_width);
+
+    // Use the tallest font of the text for every line, so lines are evenly spaced
+    let (ascent, descent, line_gap) = lines.iter()
+        .flatten()
+        .map(|g| fonts.font(g.font_index).as_scaled(scale))
+        .fold((0.0f32, 0.0f32, 0.0f32), |(a, d, g), font| {
+            (a.max(font.ascent()), d.min(font.descent()), g.max(font.line_gap()))
+        });
+    let line_height = (ascent - descent + line_gap) * options.line_spacing;
+
+    let widths: Vec<f32> = lines.iter().map(|line| line_width(line)).collect();
+    let block_width = widths.iter().copied().fold(0.0, f32::max);
+
+    let mut glyphs = vec![];
+    for (i, (line, width)) in lines.iter().zip(widths.iter()).enumerate() {
+        let offset = match options.align {
+            Align::Left => 0.0,
+            Align::Center => (block_width - width) / 2.0,
+            Align::Right => block_width - width,
+        };
+        let baseline = ascent + i as f32 * line_height;
+
+        glyphs.extend(line.iter().map(|g| PositionedGlyph {
+            font_index: g.font_index,
+            glyph: g.glyph,
+            pos: [offset + g.x, baseline],
+            advance: g.advance,
+            line: i,
+        }));
+    }
+
+    let height = if lines.is_empty() { 0.0 } else { ascent - descent + (lines.len() - 1) as f32 * line_height };
+
+    Layout {
+        glyphs,
+        size: [block_width, height],
+        line_count: lines.len(),
+    }
+}
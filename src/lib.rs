@@ -0,0 +1,23 @@
+pub mod adapter;
+pub mod bloom;
+pub mod clock;
+pub mod color;
+pub mod config;
+pub mod font;
+pub mod graph;
+pub mod headless;
+pub mod hot_reload;
+pub mod layout;
+pub mod noise;
+pub mod noise_lib;
+pub mod overlay;
+pub mod pacing;
+pub mod palette;
+pub mod pipeline_cache;
+pub mod post;
+pub mod record;
+pub mod renderer;
+pub mod sdf;
+pub mod text;
+pub mod timing;
+pub mod tonemap;
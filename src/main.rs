@@ -1,576 +1,225 @@
-use ab_glyph::{FontRef, PxScale};
-use imageproc::{drawing::{draw_text_mut, text_size}, image::{Rgba, RgbaImage}};
-use rand::Rng;
-use wgpu::util::DeviceExt;
 use winit::{
     application::ApplicationHandler, 
-    event::WindowEvent, 
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, StartCause, WindowEvent}, 
     event_loop::{
         ActiveEventLoop,
+        ControlFlow,
         EventLoop,
     }, 
-    window::{Window, WindowId},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Fullscreen, Window, WindowId},
 };
 
-use std::sync::Arc;
-
-fn get_text(text: &str) -> RgbaImage {
-    let font = FontRef::try_from_slice(include_bytes!("./IosevkaTermNerdFont-Bold.ttf")).unwrap();
-    let scale = PxScale::from(256.0);
-    let (w, _h) = text_size(scale, &font, text);
-
-    let mut img = RgbaImage::new(w, scale.y as u32);
-    draw_text_mut(&mut img, Rgba([255, 255, 255, 255]), 0, 0, scale, &font, text);
-
-    img
-}
-
-#[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    pos: [f32; 2],
-    uv: [f32; 2],
-}
-
-impl Vertex {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    time: f32,
-    aspect: f32,
-}
-
-#[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Colors {
-    bg_color: [f32; 3],
-    _pad: u32,
-    fg_color: [f32; 3],
-    _pad2: u32,
-}
-
-impl Colors {
-    fn new(bg_color: [f32; 3], fg_color: [f32; 3]) -> Self {
-        Colors { bg_color, _pad: 0, fg_color, _pad2: 0 }
-    }
-}
-
-const QUAD: &[Vertex] = &[
-    Vertex { pos: [-1.0, -1.0], uv: [0.0, 0.0] },
-    Vertex { pos: [-1.0,  1.0], uv: [0.0, 1.0] },
-    Vertex { pos: [ 1.0, -1.0], uv: [1.0, 0.0] },
-    Vertex { pos: [ 1.0,  1.0], uv: [1.0, 1.0] },
-];
-
-struct PipelineBuilder<'a> {
-    device: &'a wgpu::Device, 
-    bind_groups: Vec<&'a wgpu::BindGroupLayout>, 
-    blending: Option<wgpu::BlendState>,
-    buffers: Vec<wgpu::VertexBufferLayout<'a>>,
-    shader_code: &'a str,
-    color_format: wgpu::TextureFormat,
-}
-
-impl<'a> PipelineBuilder<'a> {
-    fn new(device: &'a wgpu::Device, color_format: wgpu::TextureFormat, shader_code: &'a str) -> Self {
-        PipelineBuilder { 
-            color_format,
-            device,
-            bind_groups: vec![],
-            buffers: vec![],
-            shader_code,
-            blending: None,
-        }
-    }
-
-    fn with_blending(mut self, blending: wgpu::BlendState) -> Self {
-        self.blending = Some(blending);
-        self
-    }
-
-    fn with_bind_group(mut self, bind_group: &'a wgpu::BindGroupLayout) -> Self {
-        self.bind_groups.push(bind_group);
-        self
-    }
-
-    fn with_buffer(mut self, buffer: wgpu::VertexBufferLayout<'a>) -> Self {
-        self.buffers.push(buffer);
-        self
-    }
-
-    fn build(self) -> wgpu::RenderPipeline {
-        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(self.shader_code.into()),
-        });
-        
-        let pipeline_layout = self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { 
-            label: Some("some render pipeline layout"),
-            bind_group_layouts: self.bind_groups.as_slice(),
-            push_constant_ranges: &[],
-        });
-
-        self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor { 
-            label: Some("Some render pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState { 
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: self.buffers.as_slice(),
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState { 
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[
-                    Some(wgpu::ColorTargetState { 
-                        format: self.color_format,
-                        blend: self.blending,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                ],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState { 
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState { 
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        })
-    }
-}
-
-fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
-    if t < 0.0 {
-        t += 1.0;
-    }
-    if t > 1.0 {
-        t -= 1.0;
-    }
-    if t < 1.0/6.0 {
-        return p + (q - p) * 6.0 * t;
-    }
-    if t < 1.0/2.0 {
-        return q;
-    }
-    if t < 2.0/3.0 {
-        return p + (q - p) * (2.0/3.0 - t) * 6.0;
-    }
-    p
-}
-
-fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [f32; 3] {
-    let r: f32;
-    let g: f32;
-    let b: f32;
-
-    if s == 0.0 {
-        r = l;
-        g = l;
-        b = l;
-    } else {
-        let q = if l < 0.5 {
-            l * (1.0 + s)
-        } else { 
-            l + s - l * s
-        };
-        let p = 2.0 * l - q;
-        r = hue_to_rgb(p, q, h + 1.0/3.0);
-        g = hue_to_rgb(p, q, h);
-        b = hue_to_rgb(p, q, h - 1.0/3.0);
-    }
-
-    return [r, g, b];
-}
-
-fn get_back_texture(device: &wgpu::Device, size: (u32, u32)) -> (wgpu::Texture, wgpu::BindGroup) {
-    let texture_size = wgpu::Extent3d {
-        width: size.0,
-        height: size.1,
-        depth_or_array_layers: 1,
-    };
-
-    let texture = device.create_texture(&wgpu::wgt::TextureDescriptor { 
-        label: Some("back_texture"),
-        size: texture_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        view_formats: &[],
-    });
-    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    let text_texture_sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor { 
-        label: Some("text_texture_sampler"),
-        address_mode_u: wgpu::AddressMode::MirrorRepeat,
-        address_mode_v: wgpu::AddressMode::MirrorRepeat,
-        address_mode_w: wgpu::AddressMode::MirrorRepeat,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Linear,
-        mipmap_filter: wgpu::FilterMode::Nearest,
-        ..Default::default()
-    });
-
-    let group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
-        label: Some("uniform_bind_group_layout"), 
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Texture { 
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    multisampled: false,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
-        ],
-    });
-
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { 
-        label: Some("back_texture_bind_group"),
-        layout: &group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&texture_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&text_texture_sampler),
-            },
-        ],
-    });
+use wgpu_test::{
+    adapter::list_adapters,
+    clock::Clock,
+    color::{surface_format, OutputFormat},
+    config::{Config, DEFAULT_HEADLESS_SIZE},
+    headless::{request_device_from, Headless},
+    hot_reload::{Shader, ShaderWatcher},
+    overlay::FrameStats,
+    pacing::FramePacer,
+    pipeline_cache::DiskPipelineCache,
+    post::EffectKind,
+    record::record,
+    renderer::{Colors, Renderer},
+    timing::GpuTimer,
+};
 
-    (texture, bind_group)
-}
+use std::sync::Arc;
 
 struct State {
     surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
+    output_format: OutputFormat,
     is_surface_configured: bool,
 
-    pipeline: wgpu::RenderPipeline,
-    pipeline_post: wgpu::RenderPipeline,
-    pipeline_text: wgpu::RenderPipeline,
-
-    buffer: wgpu::Buffer,
-    text_vertex_buffer: wgpu::Buffer,
-    uniform_buffer: wgpu::Buffer,
-    _color_buffer: wgpu::Buffer,
-
-    uniform_bind_group: wgpu::BindGroup,
-    texture_bind_group: wgpu::BindGroup,
-    back_texture_bind_group: wgpu::BindGroup,
+    renderer: Renderer,
+    shader_watcher: Option<ShaderWatcher>,
+    pipeline_cache: Option<DiskPipelineCache>,
 
-    back_texture: wgpu::Texture,
-    text_texture: wgpu::Texture,
-
-    timer: std::time::Instant,
+    clock: Clock,
+    pacer: FramePacer,
+    stats: FrameStats,
+    /// Start of the previous frame
+    last_frame: Option<std::time::Instant>,
 
     window: Arc<Window>,
 }
 
 impl State {
-    async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+    async fn new(window: Arc<Window>, config: &Config, colors: Colors) -> anyhow::Result<Self> {
         let window_size = window.inner_size();
+        let options = config.render.clone();
+        let watch_shaders = config.watch_shaders.clone();
+        let pipeline_cache_dir = config.pipeline_cache.clone();
 
         // Prepare GPU
 
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor { 
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window.clone()).unwrap();
-
-        let adapter = instance
-            .enumerate_adapters(wgpu::Backends::PRIMARY)
-            .into_iter()
-            .filter(|adapter| adapter.is_surface_supported(&surface))
-            .next()
-            .unwrap();
+        let instance = config.adapter.instance();
+        let surface = instance.create_surface(window.clone())?;
+        let adapter = config.adapter.select(&instance, Some(&surface))?;
 
         let adapter_info = adapter.get_info();
-        log::info!("Using adapter: {}, Backend: {:?}", adapter_info.name, adapter_info.backend);
-
-        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
-            label: None,
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
-            memory_hints: Default::default(),
-            trace: wgpu::Trace::Off,
-        }).await?;
+        let (device, queue) = request_device_from(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| !f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
+        let output_format = surface_format(&surface_caps, adapter.get_downlevel_capabilities().flags);
+        log::info!("Surface format: {:?}, drawn as {:?}", output_format.format, output_format.view_format);
+
+        let pacing = config.pacing;
+        let present_mode = pacing.present_mode.choose(pacing.pacing, &surface_caps.present_modes);
+        let preferred = pacing.present_mode.preference(pacing.pacing)[0];
+        if present_mode != preferred {
+            log::warn!("Present mode {preferred:?} is not supported, using {present_mode:?}");
+        }
+        log::info!("Frame pacing: {:?}, present mode: {present_mode:?}", pacing.pacing);
 
+        let config_clock = config.clock;
+        let show_overlay = config.overlay;
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
+            format: output_format.format,
             width: window_size.width,
             height: window_size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
+            view_formats: output_format.view_formats(),
             desired_maximum_frame_latency: 2,
         };
 
-        // Load vertex buffer
-
-        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: bytemuck::cast_slice(QUAD),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        // Load uniform buffers
-
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform buffer"),
-            contents: bytemuck::cast_slice(&[Uniforms {
-                time: 0.0,
-                aspect: config.width as f32 / config.height as f32,
-            }]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let mut rng = rand::rng();
-
-        let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Color buffer"),
-            contents: bytemuck::cast_slice(&[Colors::new(
-                hsl_to_rgb(rng.random_range(0.0..1.0), rng.random_range(0.0..1.0), 0.1),
-                hsl_to_rgb(rng.random_range(0.0..1.0), rng.random_range(0.0..1.0), 0.7),
-            )]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let text_img = get_text("Абоба");
-        let text_dimensions = text_img.dimensions();
-        let text_size = wgpu::Extent3d {
-            width: text_dimensions.0,
-            height: text_dimensions.1,
-            depth_or_array_layers: 1,
+        let pipeline_cache = pipeline_cache_dir.and_then(|dir| DiskPipelineCache::load(&device, &adapter_info, &dir));
+        let mut options = options.with_adapter(&adapter);
+        options.pipeline_cache = pipeline_cache.as_ref().map(|c| c.cache().clone());
+
+        let renderer = Renderer::new(&device, output_format.view_format, (window_size.width, window_size.height), colors, &options)?;
+
+        let shader_watcher = match watch_shaders {
+            Some(dir) => Some(ShaderWatcher::new(dir)?),
+            None => None,
         };
 
-        let text_ndc_size = (text_size.width  as f32 / window_size.width  as f32, 
-                             text_size.height as f32 / window_size.height as f32);
-
-        let text_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Text vertex buffer"),
-            contents: bytemuck::cast_slice(&[
-                Vertex { pos: [-1.0 * text_ndc_size.0, -1.0 * text_ndc_size.1], uv: [0.0, 0.0] },
-                Vertex { pos: [-1.0 * text_ndc_size.0,  1.0 * text_ndc_size.1], uv: [0.0, 1.0] },
-                Vertex { pos: [ 1.0 * text_ndc_size.0, -1.0 * text_ndc_size.1], uv: [1.0, 0.0] },
-                Vertex { pos: [ 1.0 * text_ndc_size.0,  1.0 * text_ndc_size.1], uv: [1.0, 1.0] },
-            ]),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
-        
-        let text_texture = device.create_texture(&wgpu::wgt::TextureDescriptor { 
-            label: Some("text_texture"),
-            size: text_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &text_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &text_img,
-            wgpu::TexelCopyBufferLayout { 
-                offset: 0,
-                bytes_per_row: Some(4 * text_dimensions.0),
-                rows_per_image: Some(text_dimensions.1),
-            },
-            text_size,
-        );
-
-        let text_texture_view = text_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let text_texture_sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor { 
-            label: Some("text_texture_sampler"),
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
-            label: Some("uniform_bind_group_layout"), 
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture { 
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        });
-
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { 
-            label: Some("texture_bind_group"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&text_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&text_texture_sampler),
-                },
-            ],
-        });
-
-        let (back_texture, back_texture_bind_group) = get_back_texture(&device, (window_size.width, window_size.height));
-
-        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
-            label: Some("uniform_bind_group_layout"), 
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None, 
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None, 
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor { 
-            label: Some("uniform_bind_group"),
-            layout: &uniform_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: color_buffer.as_entire_binding(),
+        let mut state = State {
+            window, surface, device,
+            queue, config, output_format, renderer,
+            shader_watcher, pipeline_cache,
+            is_surface_configured: false,
+            clock: Clock::new(&config_clock),
+            pacer: FramePacer::new(pacing.pacing),
+            stats: FrameStats::default(),
+            last_frame: None,
+        };
+
+        // Files on disk may already differ from the shaders baked into the binary
+        state.reload_shaders(&Shader::ALL);
+
+        if show_overlay {
+            state.set_overlay_visible(true);
+        }
+
+        Ok(state)
+    }
+
+    /// Rebuilds the pipelines of `shaders` from the watched files. A shader that fails
+    /// to compile is logged and its last good pipeline stays in use
+    fn reload_shaders(&mut self, shaders: &[Shader]) {
+        let Some(watcher) = &self.shader_watcher else { return };
+
+        for &shader in shaders {
+            let path = watcher.path(shader);
+
+            let source = match watcher.load(shader) {
+                Ok(source) => source,
+                Err(e) => {
+                    log::error!("{e}");
+                    continue;
                 },
-            ],
-        });
+            };
+
+            match self.renderer.reload_shader(&self.device, shader, &source) {
+                Ok(()) => log::info!("Loaded {}", path.display()),
+                Err(e) => log::error!("Keeping the previous pipeline of {}\n{}", path.display(), e.report),
+            }
+        }
+    }
+
+    fn on_key(&mut self, key: KeyCode) {
+        if self.on_clock_key(key) {
+            return;
+        }
 
-        // Load shader and define pipeline
+        if key == KeyCode::F3 {
+            self.set_overlay_visible(!self.renderer.overlay_visible());
+            return;
+        }
 
-        let pipeline = PipelineBuilder::new(&device, back_texture.format(), include_str!("./shader.wgsl"))
-            .with_buffer(Vertex::desc())
-            .with_bind_group(&uniform_bind_group_layout)
-            .build();
+        if key == KeyCode::KeyN {
+            let mode = self.renderer.noise_mode().toggled();
+            match self.renderer.set_noise_mode(&self.device, mode) {
+                Ok(()) => log::info!("Background noise: {mode:?}"),
+                Err(e) => log::error!("Unable to switch the background noise to {mode:?}\n{}", e.report),
+            }
+            return;
+        }
 
-        let text_blending = wgpu::BlendComponent { 
-            src_factor: wgpu::BlendFactor::OneMinusDst,
-            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-            ..Default::default()
+        let effect = match key {
+            KeyCode::Digit1 => 0,
+            KeyCode::Digit2 => 1,
+            KeyCode::Digit3 => 2,
+            KeyCode::Digit4 => 3,
+            KeyCode::Digit5 => 4,
+            KeyCode::Digit6 => 5,
+            KeyCode::Digit7 => 6,
+            _ => return,
         };
 
-        let pipeline_text = PipelineBuilder::new(&device, back_texture.format(), include_str!("./shader_text.wgsl"))
-            .with_buffer(Vertex::desc())
-            .with_bind_group(&texture_bind_group_layout)
-            .with_blending(wgpu::BlendState { 
-                color: text_blending,
-                alpha: text_blending, 
-            })
-            .build();
-
-        let pipeline_post = PipelineBuilder::new(&device, config.format, include_str!("./shader_post.wgsl"))
-            .with_buffer(Vertex::desc())
-            .with_bind_group(&uniform_bind_group_layout)
-            .with_bind_group(&texture_bind_group_layout)
-            .build();
-
-        Ok(State {
-            window, surface, device,
-            queue, config, pipeline, buffer,
-            uniform_buffer, uniform_bind_group,
-            texture_bind_group, pipeline_post, pipeline_text,
-            text_vertex_buffer, text_texture,
-            back_texture, back_texture_bind_group,
-            is_surface_configured: false,
-            timer: std::time::Instant::now(),
-            _color_buffer: color_buffer,
-        })
+        let kind = EffectKind::ALL[effect];
+        let enabled = self.renderer.post_mut().toggle(kind);
+        log::info!("Post effect {} {}", kind.name(), if enabled { "enabled" } else { "disabled" });
+    }
+
+    fn set_overlay_visible(&mut self, visible: bool) {
+        if let Err(e) = self.renderer.set_overlay_visible(&self.device, visible) {
+            log::error!("Unable to show the performance overlay: {e}");
+            return;
+        }
+        if visible && !GpuTimer::is_supported(&self.device) {
+            log::warn!("The adapter has no timestamp queries, the overlay can't show GPU times");
+        }
+    }
+
+    /// Pause, scrub, step and speed shortcuts, also handled while the key repeats.
+    /// Returns whether `key` was one of them
+    fn on_clock_key(&mut self, key: KeyCode) -> bool {
+        let clock = &mut self.clock;
+        match key {
+            KeyCode::Space => {
+                let paused = clock.toggle_pause();
+                log::info!("{} at {:.3}s", if paused { "Paused" } else { "Resumed" }, clock.time());
+            },
+            KeyCode::ArrowLeft => clock.seek_by(-1.0),
+            KeyCode::ArrowRight => clock.seek_by(1.0),
+            KeyCode::Comma => clock.step(-1),
+            KeyCode::Period => clock.step(1),
+            KeyCode::ArrowUp => clock.set_speed(clock.speed() * 2.0),
+            KeyCode::ArrowDown => clock.set_speed(clock.speed() / 2.0),
+            KeyCode::KeyR => {
+                clock.restart();
+                clock.set_speed(1.0);
+            },
+            _ => return false,
+        }
+
+        if matches!(key, KeyCode::ArrowUp | KeyCode::ArrowDown | KeyCode::KeyR) {
+            log::info!("Animation speed {}x", clock.speed());
+        }
+        if matches!(key, KeyCode::ArrowLeft | KeyCode::ArrowRight | KeyCode::Comma | KeyCode::Period | KeyCode::KeyR) {
+            log::info!("Animation time {:.3}s", clock.time());
+        }
+        true
     }
 
     fn on_resize(&mut self, w: u32, h: u32) {
@@ -580,125 +229,94 @@ impl State {
             self.surface.configure(&self.device, &self.config);
             self.is_surface_configured = true;
 
-            self.back_texture.destroy();
-
-            let (tex, tex_group) = get_back_texture(&self.device, (w, h));
-            self.back_texture = tex;
-            self.back_texture_bind_group = tex_group;
-
-            let (text_width, text_height) = (self.text_texture.width(), self.text_texture.height());
-
-            let text_ndc_size = (text_width  as f32 / w as f32, 
-                                 text_height as f32 / h as f32);
-
-            self.queue.write_buffer(&self.text_vertex_buffer, 0, bytemuck::cast_slice(&[
-                Vertex { pos: [-1.0 * text_ndc_size.0, -1.0 * text_ndc_size.1], uv: [0.0, 0.0] },
-                Vertex { pos: [-1.0 * text_ndc_size.0,  1.0 * text_ndc_size.1], uv: [0.0, 1.0] },
-                Vertex { pos: [ 1.0 * text_ndc_size.0, -1.0 * text_ndc_size.1], uv: [1.0, 0.0] },
-                Vertex { pos: [ 1.0 * text_ndc_size.0,  1.0 * text_ndc_size.1], uv: [1.0, 1.0] },
-            ]));
+            self.renderer.on_resize(&self.device, w, h);
         }
     }
 
     fn on_draw(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let timer = std::time::Instant::now();
-
         if !self.is_surface_configured {
             return Ok(());
         }
 
-        // Update uniform buffer
-        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Uniforms {
-            time: self.timer.elapsed().as_secs_f32(),
-            aspect: self.config.width as f32 / self.config.height as f32,
-        }]));
+        let frame_start = std::time::Instant::now();
+        let interval = self.last_frame.replace(frame_start).map(|last| frame_start - last);
+
+        if let Some(watcher) = &self.shader_watcher {
+            let changed = watcher.changed();
+            self.reload_shaders(&changed);
+        }
 
         let output = self.surface.get_current_texture()?;
-        let back_view = self.back_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let view = output.texture.create_view(&wgpu::wgt::TextureViewDescriptor::default());
+        // Waiting for the surface above is left out of the CPU time
+        let cpu_start = std::time::Instant::now();
+        let view = self.output_format.create_view(&output.texture);
 
         let mut encoder = self.device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Some encoder") });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
-                label: Some("Some render pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment { 
-                        view: &back_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations { 
-                            load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }), 
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_vertex_buffer(0, self.buffer.slice(..));
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.draw(0..QUAD.len() as u32, 0..1);
-
-            render_pass.set_pipeline(&self.pipeline_text);
-            render_pass.set_vertex_buffer(0, self.text_vertex_buffer.slice(..));
-            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-            render_pass.draw(0..4, 0..1);
+        if self.renderer.overlay_visible() {
+            if let Some(timings) = self.renderer.pass_timings() {
+                self.stats.push_gpu_times(timings);
+            }
+            self.renderer.set_overlay_text(&self.stats.text());
         }
 
-        {
-            let mut render_pass_post = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
-                label: Some("Post render pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment { 
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations { 
-                            load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }), 
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            render_pass_post.set_pipeline(&self.pipeline_post);
-            render_pass_post.set_vertex_buffer(0, self.buffer.slice(..));
-            render_pass_post.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass_post.set_bind_group(1, &self.back_texture_bind_group, &[]);
-            render_pass_post.draw(0..QUAD.len() as u32, 0..1);
-        }
+        let time = self.clock.tick(std::time::Instant::now());
+        self.renderer.render(&self.device, &self.queue, &mut encoder, &view, time);
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        let frame_time = timer.elapsed();
-        let max_time = std::time::Duration::from_secs_f64(1.0 / 60.0);
-
-        if frame_time < max_time {
-            std::thread::sleep(max_time - frame_time);
+        self.renderer.frame_submitted();
+        if let Some(interval) = interval {
+            self.stats.push(interval, cpu_start.elapsed());
         }
 
-        self.window.request_redraw();
+        self.window.pre_present_notify();
+        output.present();
 
         Ok(())
     }
+
+    /// Requests the next frame right away, or wakes the event loop up for it at the target frame rate
+    fn schedule_next_frame(&mut self, event_loop: &ActiveEventLoop) {
+        match self.pacer.frame_presented(std::time::Instant::now()) {
+            Some(next_frame) => event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame)),
+            None => self.window.request_redraw(),
+        }
+    }
 }
 
-#[derive(Default)]
 struct App {
     state: Option<State>,
+    config: Config,
+    colors: Colors,
+    error: Option<anyhow::Error>,
 }
 
 impl ApplicationHandler<()> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window_attributes = Window::default_attributes().with_title("Sus window");
+        let mut window_attributes = Window::default_attributes().with_title(self.config.title.as_str());
+        if let Some((w, h)) = self.config.size {
+            window_attributes = window_attributes.with_inner_size(PhysicalSize::new(w, h));
+        }
+        if self.config.fullscreen {
+            window_attributes = window_attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
-        self.state = Some(pollster::block_on(State::new(window)).unwrap());
+
+        match pollster::block_on(State::new(window, &self.config, self.colors)) {
+            Ok(state) => self.state = Some(state),
+            Err(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+            },
+        }
+    }
+
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if let StartCause::ResumeTimeReached { .. } = cause && let Some(state) = &self.state {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            state.window.request_redraw();
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
@@ -710,6 +328,16 @@ impl ApplicationHandler<()> for App {
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(size) => state.on_resize(size.width, size.height),
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(key), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } => state.on_key(key),
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(key), state: ElementState::Pressed, repeat: true, .. },
+                ..
+            } => {
+                state.on_clock_key(key);
+            },
             WindowEvent::RedrawRequested => {
                 match state.on_draw() {
                     Ok(_) => {},
@@ -721,20 +349,55 @@ impl ApplicationHandler<()> for App {
                         log::error!("Unable to render shit: {e}");
                     },
                 }
+
+                // An unconfigured surface gets its first frame from the resize
+                if state.is_surface_configured {
+                    state.schedule_next_frame(event_loop);
+                }
             },
             _ => {},
         }
     }
 
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(cache) = self.state.as_ref().and_then(|s| s.pipeline_cache.as_ref())
+            && let Err(e) = cache.save()
+        {
+            log::error!("Unable to save pipeline cache {}: {e}", cache.path().display());
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let mut app = App::default();
+    let config = Config::from_args();
+
+    if config.adapter.list_adapters {
+        let adapters = config.adapter.instance().enumerate_adapters(config.adapter.backends());
+        print!("{}", list_adapters(&adapters));
+        return Ok(());
+    }
+
+    let colors = config.palette.resolve();
+
+    if config.headless.is_some() || config.record.record.is_some() {
+        let size = config.size.unwrap_or(DEFAULT_HEADLESS_SIZE);
+        let mut headless = pollster::block_on(Headless::new(size, colors, &config.render, &config.adapter, config.pipeline_cache.as_deref()))?;
+        match &config.headless {
+            Some(output) => headless.render_to_png(config.clock.time, output)?,
+            None => record(&mut headless, &config.record, config.clock.time)?,
+        }
+        return headless.save_pipeline_cache();
+    }
+
+    let mut app = App { state: None, config, colors, error: None };
 
     let event_loop = EventLoop::new()?;
     event_loop.run_app(&mut app)?;
 
-    Ok(())
+    match app.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,363 @@
+//! Cached alternative to evaluating the background noise per pixel: a compute shader fills a
+//! tiling 3D volume once and the scene shader takes a single filtered sample of it
+
+use crate::{
+    hot_reload::Shader,
+    noise_lib::{with_noise_lib, NoiseBasis},
+    renderer::{capture_pipeline_error, validate_shader, PipelineError},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseMode {
+    /// Every octave evaluated per pixel, every frame
+    #[default]
+    Analytic,
+    /// Sampled from a precomputed tiling noise volume
+    Volume,
+}
+
+impl NoiseMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            NoiseMode::Analytic => NoiseMode::Volume,
+            NoiseMode::Volume => NoiseMode::Analytic,
+        }
+    }
+}
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct NoiseOptions {
+    /// How the background noise is evaluated
+    #[arg(long, value_enum, default_value_t)]
+    pub noise: NoiseMode,
+
+    /// Noise function the background is built from
+    #[arg(long, value_enum, default_value_t)]
+    pub noise_basis: NoiseBasis,
+
+    /// Edge of the noise volume in texels, a multiple of 8 up to 256
+    #[arg(long, default_value_t = 128, value_parser = parse_volume_size)]
+    pub noise_volume_size: u32,
+}
+
+impl Default for NoiseOptions {
+    fn default() -> Self {
+        NoiseOptions {
+            noise: NoiseMode::default(),
+            noise_basis: NoiseBasis::default(),
+            noise_volume_size: 128,
+        }
+    }
+}
+
+fn parse_volume_size(s: &str) -> Result<u32, String> {
+    let size: u32 = s.parse().map_err(|e| format!("{e}"))?;
+    if size == 0 || size > 256 || !size.is_multiple_of(NOISE_VOLUME_CELL) {
+        return Err(format!("volume size must be a multiple of {NOISE_VOLUME_CELL} up to 256"));
+    }
+    Ok(size)
+}
+
+/// Smallest format of the volume that is still filterable
+pub const NOISE_VOLUME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+/// Texels per first octave cell. The finest octaves get less than a texel, which is where
+/// the volume loses detail compared to the analytic noise
+pub const NOISE_VOLUME_CELL: u32 = 8;
+
+/// Matches `@workgroup_size` in `shader_noise.wgsl`
+const WORKGROUP_SIZE: u32 = 4;
+
+/// Units of noise a volume of `size` texels covers before repeating
+pub fn noise_period(size: u32) -> f32 {
+    (size / NOISE_VOLUME_CELL) as f32
+}
+
+/// Texel pairs per padded row of the staging buffer, see `ROW_PAIRS` in `shader_noise.wgsl`
+fn row_pairs(size: u32) -> u32 {
+    padded_bytes_per_row(size) / 4
+}
+
+fn padded_bytes_per_row(size: u32) -> u32 {
+    (size * 2).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+fn storage_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("noise_storage_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Layout of the volume at binding 0 and its repeating sampler at binding 1, group 1 of `fs_volume`
+pub fn volume_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("noise_volume_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Checks a `Shader::Noise` source without building the pipeline, for when there is no volume yet
+pub fn validate_noise_shader(source: &str, noise_lib: &str) -> Result<(), PipelineError> {
+    validate_shader(Shader::Noise.file_name(), &with_noise_lib(source, noise_lib)).map(|_| ())
+}
+
+fn noise_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    size: u32,
+    basis: NoiseBasis,
+    cache: Option<&wgpu::PipelineCache>,
+    source: &str,
+    noise_lib: &str,
+) -> Result<wgpu::ComputePipeline, PipelineError> {
+    let label = Shader::Noise.file_name();
+    let source = with_noise_lib(source, noise_lib);
+    validate_shader(label, &source)?;
+
+    capture_pipeline_error(device, label, || {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("noise pipeline layout"),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &[
+                    ("PERIOD", noise_period(size) as f64),
+                    ("SIZE", size as f64),
+                    ("ROW_PAIRS", row_pairs(size) as f64),
+                    ("NOISE_BASIS", basis.shader_id() as f64),
+                ],
+                ..Default::default()
+            },
+            cache,
+        })
+    })
+}
+
+/// The noise volume and the compute pipeline filling it.
+/// The pipeline writes into a buffer that is then copied into the volume: the GL backend binds
+/// 3D storage textures one layer at a time, so writing the volume directly loses all but the first
+pub struct NoiseVolume {
+    pipeline: wgpu::ComputePipeline,
+    storage_layout: wgpu::BindGroupLayout,
+    cache: Option<wgpu::PipelineCache>,
+    basis: NoiseBasis,
+    /// Last sources that compiled, each is rebuilt with the other when reloaded
+    source: String,
+    noise_lib: String,
+
+    texels: wgpu::Buffer,
+    texture: wgpu::Texture,
+    storage_bind_group: wgpu::BindGroup,
+    bind_group: wgpu::BindGroup,
+    size: u32,
+    /// Set until the volume is filled with the current pipeline
+    dirty: bool,
+}
+
+impl NoiseVolume {
+    /// `layout` is the `volume_bind_group_layout` the scene pipeline samples through, `source` and
+    /// `noise_lib` the WGSL of `Shader::Noise` and `Shader::NoiseLib`.
+    /// Fails on devices without compute shaders and for a `basis` that doesn't tile
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        size: u32,
+        basis: NoiseBasis,
+        source: &str,
+        noise_lib: &str,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Result<Self, PipelineError> {
+        if !basis.tiles() {
+            let message = format!("{basis:?} noise doesn't tile, so it can't fill the noise volume");
+            return Err(PipelineError {
+                label: Shader::Noise.file_name().into(),
+                span: None,
+                report: message.clone(),
+                message,
+            });
+        }
+
+        let storage_layout = storage_bind_group_layout(device);
+        let pipeline = noise_pipeline(device, &storage_layout, size, basis, cache, source, noise_lib)?;
+
+        let texture = device.create_texture(&wgpu::wgt::TextureDescriptor {
+            label: Some("noise_volume"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: NOISE_VOLUME_FORMAT,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let texels = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("noise_texels"),
+            size: padded_bytes_per_row(size) as u64 * size as u64 * size as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("noise_storage_bind_group"),
+            layout: &storage_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: texels.as_entire_binding(),
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor {
+            label: Some("noise_volume_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("noise_volume_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Ok(NoiseVolume {
+            pipeline, storage_layout,
+            cache: cache.cloned(),
+            basis,
+            source: source.into(),
+            noise_lib: noise_lib.into(),
+            texels, texture,
+            storage_bind_group, bind_group, size,
+            dirty: true,
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn period(&self) -> f32 {
+        noise_period(self.size)
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Fills the volume if it isn't yet. Submitted on its own, ahead of the frame that samples it
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Noise encoder") });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Noise compute pass"),
+                timestamp_writes: None,
+            });
+
+            let groups = self.size.div_ceil(WORKGROUP_SIZE);
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.storage_bind_group, &[]);
+            compute_pass.dispatch_workgroups((self.size / 2).div_ceil(WORKGROUP_SIZE), groups, groups);
+        }
+
+        encoder.copy_buffer_to_texture(
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.texels,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row(self.size)),
+                    rows_per_image: Some(self.size),
+                },
+            },
+            self.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.size,
+                height: self.size,
+                depth_or_array_layers: self.size,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.dirty = false;
+    }
+
+    /// Rebuilds the compute pipeline and refills the volume on the next `prepare`
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        let (noise, noise_lib) = match shader {
+            Shader::Noise => (source, self.noise_lib.as_str()),
+            Shader::NoiseLib => (self.source.as_str(), source),
+            _ => return Ok(()),
+        };
+
+        self.pipeline = noise_pipeline(device, &self.storage_layout, self.size, self.basis, self.cache.as_ref(), noise, noise_lib)?;
+        match shader {
+            Shader::Noise => self.source = source.into(),
+            _ => self.noise_lib = source.into(),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! CPU mirror of `shader_noise_lib.wgsl`, the noise functions the backgrounds are built from.
+//! Every function here computes the same thing as its WGSL namesake, step by step, so tests can
+//! check the shader against reference values. Keep the two in sync
+
+/// Noise function a background is built from
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseBasis {
+    /// Random values at the lattice points, interpolated
+    Value,
+    /// Random gradients at the lattice points
+    #[default]
+    Perlin,
+    /// Random gradients on a simplex lattice, without the axis aligned look. Doesn't tile
+    Simplex,
+    /// Distance to the nearest of randomly scattered points, cell-like
+    Worley,
+}
+
+impl NoiseBasis {
+    pub const ALL: [NoiseBasis; 4] = [NoiseBasis::Value, NoiseBasis::Perlin, NoiseBasis::Simplex, NoiseBasis::Worley];
+
+    /// Matches the `BASIS_*` constants in `shader_noise_lib.wgsl`
+    pub fn shader_id(self) -> u32 {
+        match self {
+            NoiseBasis::Value => 0,
+            NoiseBasis::Perlin => 1,
+            NoiseBasis::Simplex => 2,
+            NoiseBasis::Worley => 3,
+        }
+    }
+
+    /// Whether the noise can repeat after a whole number of lattice cells, which the noise volume needs
+    pub fn tiles(self) -> bool {
+        self != NoiseBasis::Simplex
+    }
+}
+
+/// `source` with the noise library appended. WGSL declarations can come in any order,
+/// so the library goes last and errors in `source` keep their line numbers
+pub fn with_noise_lib(source: &str, noise_lib: &str) -> String {
+    format!("{source}\n{noise_lib}")
+}
+
+pub type Vec3 = [f32; 3];
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+/// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski and Olano
+pub fn hash_u32(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+pub fn hash_cell(cell: [i32; 3]) -> u32 {
+    hash_u32(cell[0] as u32 ^ hash_u32(cell[1] as u32 ^ hash_u32(cell[2] as u32)))
+}
+
+/// Uniform in [0, 1)
+pub fn hash_to_unit(h: u32) -> f32 {
+    (h >> 8) as f32 / 16777216.0
+}
+
+/// Lattice cells repeat every `period` cells along each axis, 0 doesn't repeat
+pub fn wrap_cell(cell: [i32; 3], period: i32) -> [i32; 3] {
+    if period <= 0 {
+        return cell;
+    }
+    cell.map(|c| c.rem_euclid(period))
+}
+
+/// Quintic fade, with zero first and second derivatives at the lattice points
+pub fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn offset_cell(cell: [i32; 3], offset: [i32; 3]) -> [i32; 3] {
+    [cell[0] + offset[0], cell[1] + offset[1], cell[2] + offset[2]]
+}
+
+/// Trilinear interpolation of `corner` at the 8 corners of a cell, weighted by `t`
+fn interpolate(t: Vec3, corner: impl Fn([i32; 3]) -> f32) -> f32 {
+    let x00 = mix(corner([0, 0, 0]), corner([1, 0, 0]), t[0]);
+    let x10 = mix(corner([0, 1, 0]), corner([1, 1, 0]), t[0]);
+    let x01 = mix(corner([0, 0, 1]), corner([1, 0, 1]), t[0]);
+    let x11 = mix(corner([0, 1, 1]), corner([1, 1, 1]), t[0]);
+    mix(mix(x00, x10, t[1]), mix(x01, x11, t[1]), t[2])
+}
+
+fn floor_cell(p: Vec3) -> ([i32; 3], Vec3) {
+    let floor = p.map(f32::floor);
+    (floor.map(|c| c as i32), sub(p, floor))
+}
+
+/// In [0, 1]
+pub fn value_noise(p: Vec3, period: i32) -> f32 {
+    let (cell, f) = floor_cell(p);
+    interpolate(f.map(fade), |offset| hash_to_unit(hash_cell(wrap_cell(offset_cell(cell, offset), period))))
+}
+
+/// Dot product of `f` with one of the 12 gradients pointing to the edges of a cube, picked by `h`
+pub fn gradient(h: u32, f: Vec3) -> f32 {
+    let h = h & 15;
+    let u = if h < 8 { f[0] } else { f[1] };
+    let v = if h < 4 {
+        f[1]
+    } else if h == 12 || h == 14 {
+        f[0]
+    } else {
+        f[2]
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// In [-1, 1]
+pub fn perlin_noise(p: Vec3, period: i32) -> f32 {
+    let (cell, f) = floor_cell(p);
+    interpolate(f.map(fade), |offset| {
+        let h = hash_cell(wrap_cell(offset_cell(cell, offset), period));
+        gradient(h, sub(f, offset.map(|o| o as f32)))
+    })
+}
+
+/// Contribution of a simplex corner at `x` from the sample point
+fn simplex_corner(cell: [i32; 3], x: Vec3) -> f32 {
+    let t = 0.6 - dot(x, x);
+    if t <= 0.0 {
+        return 0.0;
+    }
+    let t2 = t * t;
+    t2 * t2 * gradient(hash_cell(cell), x)
+}
+
+/// In [-1, 1]
+pub fn simplex_noise(p: Vec3) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    // Skew into the lattice of cubes split into 6 simplices, and back
+    let s = (p[0] + p[1] + p[2]) * F3;
+    let i = p.map(|c| (c + s).floor());
+    let t = (i[0] + i[1] + i[2]) * G3;
+    let x0 = sub(p, i.map(|c| c - t));
+
+    // Which simplex of the cube, by the order of the coordinates
+    let g = [
+        (x0[0] >= x0[1]) as i32 as f32,
+        (x0[1] >= x0[2]) as i32 as f32,
+        (x0[2] >= x0[0]) as i32 as f32,
+    ];
+    let l = g.map(|c| 1.0 - c);
+    let i1 = [g[0].min(l[2]), g[1].min(l[0]), g[2].min(l[1])];
+    let i2 = [g[0].max(l[2]), g[1].max(l[0]), g[2].max(l[1])];
+
+    let x1 = [x0[0] - i1[0] + G3, x0[1] - i1[1] + G3, x0[2] - i1[2] + G3];
+    let x2 = [x0[0] - i2[0] + 2.0 * G3, x0[1] - i2[1] + 2.0 * G3, x0[2] - i2[2] + 2.0 * G3];
+    let x3 = x0.map(|c| c - 1.0 + 3.0 * G3);
+
+    let cell = i.map(|c| c as i32);
+    let n = simplex_corner(cell, x0)
+        + simplex_corner(offset_cell(cell, i1.map(|c| c as i32)), x1)
+        + simplex_corner(offset_cell(cell, i2.map(|c| c as i32)), x2)
+        + simplex_corner(offset_cell(cell, [1, 1, 1]), x3);
+    32.0 * n
+}
+
+/// Distance to the nearest feature point, one per cell. Mostly in [0, 1]
+pub fn worley_noise(p: Vec3, period: i32) -> f32 {
+    let (cell, f) = floor_cell(p);
+
+    let mut min_dist = 8.0f32;
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let offset = [x, y, z];
+                let h = hash_cell(wrap_cell(offset_cell(cell, offset), period));
+                let point = [hash_to_unit(h), hash_to_unit(hash_u32(h)), hash_to_unit(hash_u32(hash_u32(h)))];
+                let d = [
+                    offset[0] as f32 + point[0] - f[0],
+                    offset[1] as f32 + point[1] - f[1],
+                    offset[2] as f32 + point[2] - f[2],
+                ];
+                min_dist = min_dist.min(dot(d, d));
+            }
+        }
+    }
+    min_dist.sqrt()
+}
+
+/// `basis` mapped to [0, 1]
+pub fn noise_basis(basis: NoiseBasis, p: Vec3, period: i32) -> f32 {
+    match basis {
+        NoiseBasis::Value => value_noise(p, period),
+        NoiseBasis::Perlin => perlin_noise(p, period) * 0.5 + 0.5,
+        NoiseBasis::Simplex => simplex_noise(p) * 0.5 + 0.5,
+        NoiseBasis::Worley => 1.0 - worley_noise(p, period),
+    }
+}
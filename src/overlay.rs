@@ -0,0 +1,203 @@
+//! Performance overlay: frame rate, CPU frame time, GPU time of every pass and a graph
+//! of recent frame times, drawn as text in the corner of the output
+
+use std::{collections::VecDeque, fmt::Write, sync::Arc, time::Duration};
+
+use crate::{
+    font::FontChain,
+    graph::{Pass, PassContext},
+    hot_reload::Shader,
+    layout::Align,
+    renderer::{Multisample, PipelineError},
+    text::{TextAnchor, TextOptions, TextRenderer},
+};
+
+pub const OVERLAY_FONT_SIZE: f32 = 16.0;
+
+/// Distance of the overlay from the top left corner of the output, in pixels
+pub const OVERLAY_MARGIN: f32 = 12.0;
+
+/// Frames the averages and the graph cover
+pub const HISTORY: usize = 120;
+
+/// Columns of the frame time graph
+pub const GRAPH_WIDTH: usize = 40;
+
+/// Weight of the newest measurement in the smoothed GPU times
+const GPU_SMOOTHING: f64 = 0.1;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Short name of a render graph pass, "Scene render pass" is "scene"
+pub fn pass_name(label: &str) -> String {
+    label.strip_suffix(" render pass").unwrap_or(label).to_lowercase()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FrameTime {
+    interval: Duration,
+    cpu: Duration,
+}
+
+/// Rolling window of frame times shown by the overlay
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    frames: VecDeque<FrameTime>,
+    /// Smoothed GPU time of every pass in milliseconds, by `pass_name`
+    gpu: Vec<(String, f64)>,
+}
+
+impl FrameStats {
+    /// `interval` since the previous frame started, `cpu` spent recording and submitting this one
+    pub fn push(&mut self, interval: Duration, cpu: Duration) {
+        if self.frames.len() == HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(FrameTime { interval, cpu });
+    }
+
+    /// Newest GPU time of every pass, labeled as in the render graph. Starts over
+    /// when the passes change
+    pub fn push_gpu_times<'a>(&mut self, times: impl IntoIterator<Item = (&'a str, Duration)>) {
+        let times: Vec<(String, f64)> = times.into_iter().map(|(label, time)| (pass_name(label), ms(time))).collect();
+
+        let same_passes = times.len() == self.gpu.len() && times.iter().zip(&self.gpu).all(|(a, b)| a.0 == b.0);
+        if !same_passes {
+            self.gpu = times;
+            return;
+        }
+
+        for ((_, average), (_, time)) in self.gpu.iter_mut().zip(times) {
+            *average += (time - *average) * GPU_SMOOTHING;
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        let total: Duration = self.frames.iter().map(|f| f.interval).sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.frames.len() as f64 / total.as_secs_f64()
+    }
+
+    /// Average time between frames
+    pub fn frame_time(&self) -> Duration {
+        self.average(|f| f.interval)
+    }
+
+    /// Average CPU time of a frame
+    pub fn cpu_time(&self) -> Duration {
+        self.average(|f| f.cpu)
+    }
+
+    fn average(&self, time: impl Fn(&FrameTime) -> Duration) -> Duration {
+        match self.frames.len() {
+            0 => Duration::ZERO,
+            n => self.frames.iter().map(time).sum::<Duration>() / n as u32,
+        }
+    }
+
+    /// Smoothed GPU time of every pass in milliseconds, empty until one is measured
+    pub fn gpu_times(&self) -> &[(String, f64)] {
+        &self.gpu
+    }
+
+    /// Last `width` frame intervals as bars scaled to the longest of them, and that longest interval
+    pub fn graph(&self, width: usize) -> (String, Duration) {
+        let shown = self.frames.iter().skip(self.frames.len().saturating_sub(width));
+        let max = shown.clone().map(|f| f.interval).max().unwrap_or_default();
+
+        let graph = shown
+            .map(|f| {
+                let level = if max.is_zero() { 0.0 } else { f.interval.as_secs_f64() / max.as_secs_f64() };
+                BARS[(level * (BARS.len() - 1) as f64).round() as usize]
+            })
+            .collect();
+        (graph, max)
+    }
+
+    /// What the overlay shows
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "{:<10}{:>7.1}", "fps", self.fps());
+        let _ = writeln!(text, "{:<10}{:>7.2} ms", "frame", ms(self.frame_time()));
+        let _ = writeln!(text, "{:<10}{:>7.2} ms", "cpu", ms(self.cpu_time()));
+
+        if self.gpu.is_empty() {
+            let _ = writeln!(text, "{:<10}{:>7}", "gpu", "n/a");
+        } else {
+            let _ = writeln!(text, "{:<10}{:>7.2} ms", "gpu", self.gpu.iter().map(|(_, time)| time).sum::<f64>());
+            for (name, time) in &self.gpu {
+                let _ = writeln!(text, "  {name:<8}{time:>7.2} ms");
+            }
+        }
+
+        let (graph, max) = self.graph(GRAPH_WIDTH);
+        let _ = write!(text, "{graph} {:.1} ms", ms(max));
+        text
+    }
+}
+
+/// Draws `FrameStats::text` over the final image, in the corner of the output
+pub struct OverlayPass {
+    text: TextRenderer,
+    visible: bool,
+}
+
+impl OverlayPass {
+    /// Draws with `fonts` of the scene text. Without --font they start with the monospaced
+    /// fallbacks so the columns line up
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        cache: Option<&wgpu::PipelineCache>,
+        fonts: Arc<FontChain>,
+    ) -> anyhow::Result<Self> {
+        let options = TextOptions {
+            text: String::new(),
+            font_size: OVERLAY_FONT_SIZE,
+            align: Align::Left,
+            ..Default::default()
+        };
+
+        let mut text = TextRenderer::new(device, format, Multisample::new(device, format, 1, None), cache, fonts, &options)?;
+        text.set_anchor(TextAnchor::TopLeft([OVERLAY_MARGIN; 2]));
+        text.set_flip_y(true);
+        Ok(OverlayPass { text, visible: true })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text.set_text(text);
+    }
+}
+
+impl Pass for OverlayPass {
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, output_size: (u32, u32)) {
+        if self.visible {
+            self.text.prepare(device, queue, output_size);
+        }
+    }
+
+    fn record(&self, ctx: &mut PassContext) {
+        if self.visible {
+            let mut render_pass = ctx.begin_render_pass();
+            self.text.draw(&mut render_pass);
+        }
+    }
+
+    fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        self.text.reload_shader(device, shader, source)
+    }
+}
@@ -0,0 +1,114 @@
+//! Frame pacing of the window: when the next frame is drawn and how it's presented
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pacing {
+    /// Draw whenever the previous frame is presented, the present mode waits for the display
+    Vsync,
+    /// Draw as fast as the GPU allows
+    Uncapped,
+    /// Frames per second, scheduled with `ControlFlow::WaitUntil`
+    Target(f64),
+}
+
+impl Pacing {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "vsync" => Ok(Pacing::Vsync),
+            "uncapped" => Ok(Pacing::Uncapped),
+            fps => match fps.parse::<f64>() {
+                Ok(fps) if fps.is_finite() && fps > 0.0 => Ok(Pacing::Target(fps)),
+                _ => Err("pacing must be vsync, uncapped or a positive frame rate".into()),
+            },
+        }
+    }
+
+    /// Time between frames of a target frame rate
+    pub fn frame_period(self) -> Option<Duration> {
+        match self {
+            Pacing::Target(fps) => Some(Duration::from_secs_f64(1.0 / fps)),
+            Pacing::Vsync | Pacing::Uncapped => None,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Fifo with vsync pacing, otherwise Mailbox or Immediate
+    #[default]
+    Auto,
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentMode {
+    /// Modes tried in order, ending with Fifo that every surface supports
+    pub fn preference(self, pacing: Pacing) -> &'static [wgpu::PresentMode] {
+        use wgpu::PresentMode::{Fifo, Immediate, Mailbox};
+
+        match self {
+            PresentMode::Auto if pacing == Pacing::Vsync => &[Fifo],
+            PresentMode::Auto | PresentMode::Mailbox => &[Mailbox, Immediate, Fifo],
+            PresentMode::Immediate => &[Immediate, Mailbox, Fifo],
+            PresentMode::Fifo => &[Fifo],
+        }
+    }
+
+    /// First preferred mode among the `supported` ones of the surface
+    pub fn choose(self, pacing: Pacing, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        self.preference(pacing)
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .or_else(|| supported.first().copied())
+            .unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct PacingOptions {
+    /// When frames are drawn: vsync follows the display refresh rate, uncapped draws as fast
+    /// as possible, a number is a target frame rate
+    #[arg(long, value_name = "vsync|uncapped|FPS", default_value = "vsync", value_parser = Pacing::parse)]
+    pub pacing: Pacing,
+
+    /// Presentation mode of the window surface, falls back to the closest supported one
+    #[arg(long, value_enum, default_value_t = PresentMode::Auto)]
+    pub present_mode: PresentMode,
+}
+
+impl Default for PacingOptions {
+    fn default() -> Self {
+        PacingOptions {
+            pacing: Pacing::Vsync,
+            present_mode: PresentMode::Auto,
+        }
+    }
+}
+
+/// Schedule of the window frames
+#[derive(Clone, Debug)]
+pub struct FramePacer {
+    period: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(pacing: Pacing) -> Self {
+        FramePacer { period: pacing.frame_period(), next_frame: None }
+    }
+
+    /// When to draw the frame after the one presented at `now`, `None` for right away.
+    /// Keeps to the schedule of the target frame rate without catching up on missed frames
+    pub fn frame_presented(&mut self, now: Instant) -> Option<Instant> {
+        let period = self.period?;
+        let next = match self.next_frame {
+            Some(previous) => (previous + period).max(now),
+            None => now + period,
+        };
+        self.next_frame = Some(next);
+        Some(next)
+    }
+}
@@ -0,0 +1,137 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::renderer::Colors;
+
+pub const SEED_ENV: &str = "WGPU_TEST_SEED";
+pub const BG_ENV: &str = "WGPU_TEST_BG";
+pub const FG_ENV: &str = "WGPU_TEST_FG";
+
+fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
+    if t < 0.0 {
+        t += 1.0;
+    }
+    if t > 1.0 {
+        t -= 1.0;
+    }
+    if t < 1.0/6.0 {
+        return p + (q - p) * 6.0 * t;
+    }
+    if t < 1.0/2.0 {
+        return q;
+    }
+    if t < 2.0/3.0 {
+        return p + (q - p) * (2.0/3.0 - t) * 6.0;
+    }
+    p
+}
+
+/// Returns sRGB encoded channels, like the hex colors
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [f32; 3] {
+    let r: f32;
+    let g: f32;
+    let b: f32;
+
+    if s == 0.0 {
+        r = l;
+        g = l;
+        b = l;
+    } else {
+        let q = if l < 0.5 {
+            l * (1.0 + s)
+        } else {
+            l + s - l * s
+        };
+        let p = 2.0 * l - q;
+        r = hue_to_rgb(p, q, h + 1.0/3.0);
+        g = hue_to_rgb(p, q, h);
+        b = hue_to_rgb(p, q, h - 1.0/3.0);
+    }
+
+    [r, g, b]
+}
+
+/// Parses `#rrggbb`, `#rgb` (the `#` is optional) or CSS-like `hsl(<deg>, <sat>%, <light>%)`
+pub fn parse_color(s: &str) -> anyhow::Result<[f32; 3]> {
+    let s = s.trim();
+
+    if let Some(args) = s.strip_prefix("hsl(").and_then(|s| s.strip_suffix(')')) {
+        let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        anyhow::ensure!(parts.len() == 3, "Expected 3 components in {s:?}");
+
+        let h: f32 = parts[0].trim_end_matches("deg").parse()?;
+        let sat: f32 = parts[1].trim_end_matches('%').parse()?;
+        let light: f32 = parts[2].trim_end_matches('%').parse()?;
+        anyhow::ensure!((0.0..=100.0).contains(&sat) && (0.0..=100.0).contains(&light), "Saturation and lightness must be in 0..100% in {s:?}");
+
+        return Ok(hsl_to_rgb(h.rem_euclid(360.0) / 360.0, sat / 100.0, light / 100.0));
+    }
+
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    anyhow::ensure!(hex.chars().all(|c| c.is_ascii_hexdigit()), "Invalid color {s:?}, expected #rrggbb or hsl(h, s%, l%)");
+
+    let channel = |digits: &str| -> anyhow::Result<f32> {
+        let value = u8::from_str_radix(digits, 16)?;
+        Ok(value as f32 / 255.0)
+    };
+
+    match hex.len() {
+        6 => Ok([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        3 => {
+            let expand = |i: usize| hex[i..i + 1].repeat(2);
+            Ok([channel(&expand(0))?, channel(&expand(1))?, channel(&expand(2))?])
+        },
+        _ => anyhow::bail!("Invalid color {s:?}, expected #rrggbb or hsl(h, s%, l%)"),
+    }
+}
+
+/// Dark background and light foreground with random hue and saturation derived from `seed`
+pub fn seeded_colors(seed: u64) -> Colors {
+    // A fixed algorithm, unlike `StdRng`, so a seed gives the same palette on every platform and rand version
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    Colors::new(
+        hsl_to_rgb(rng.random_range(0.0..1.0), rng.random_range(0.0..1.0), 0.1),
+        hsl_to_rgb(rng.random_range(0.0..1.0), rng.random_range(0.0..1.0), 0.7),
+    )
+}
+
+/// How the background and foreground colors are picked.
+/// Explicit colors take priority over the ones generated from the seed
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PaletteOptions {
+    /// Seed for the generated palette, a random one is picked and logged if not set
+    #[arg(long, env = SEED_ENV)]
+    pub seed: Option<u64>,
+
+    /// Background color, as #rrggbb or hsl(h, s%, l%)
+    #[arg(long, env = BG_ENV, value_name = "COLOR", value_parser = parse_color_arg)]
+    pub bg: Option<[f32; 3]>,
+
+    /// Foreground color, as #rrggbb or hsl(h, s%, l%)
+    #[arg(long, env = FG_ENV, value_name = "COLOR", value_parser = parse_color_arg)]
+    pub fg: Option<[f32; 3]>,
+}
+
+/// `parse_color` as a clap value parser
+pub(crate) fn parse_color_arg(s: &str) -> Result<[f32; 3], String> {
+    parse_color(s).map_err(|e| e.to_string())
+}
+
+impl PaletteOptions {
+    /// Picks a random seed if none was given and logs it, so the run can be reproduced
+    pub fn resolve(&self) -> Colors {
+        let seed = self.seed.unwrap_or_else(|| rand::rng().random());
+        log::info!("Palette seed: {seed} (pass --seed {seed} or {SEED_ENV}={seed} to reproduce)");
+
+        let mut colors = seeded_colors(seed);
+        if let Some(bg) = self.bg {
+            colors.bg_color = bg;
+        }
+        if let Some(fg) = self.fg {
+            colors.fg_color = fg;
+        }
+
+        colors
+    }
+}
@@ -0,0 +1,78 @@
+//! Driver pipeline cache kept on disk between runs, on backends with `Features::PIPELINE_CACHE`
+
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PIPELINE_CACHE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/pipeline-cache");
+
+/// Cache file of `adapter_info`, `None` when its backend has no pipeline cache. The driver is part
+/// of the name, so an update starts from an empty cache instead of whatever the new driver makes of the old one
+pub fn cache_file_name(adapter_info: &wgpu::AdapterInfo) -> Option<String> {
+    let key = wgpu::util::pipeline_cache_key(adapter_info)?;
+    let driver: String = format!("{}_{}", adapter_info.driver, adapter_info.driver_info)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '-' })
+        .collect();
+    Some(format!("{key}_{driver}.bin"))
+}
+
+pub struct DiskPipelineCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+}
+
+impl DiskPipelineCache {
+    /// Opens the cache of the adapter in `dir`, empty if there is no usable file yet.
+    /// `None` when `device` wasn't opened with `Features::PIPELINE_CACHE`
+    pub fn load(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo, dir: &Path) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            log::info!("Pipeline cache is not supported by the {:?} backend", adapter_info.backend);
+            return None;
+        }
+
+        let path = dir.join(cache_file_name(adapter_info)?);
+        let data = match std::fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Unable to read pipeline cache {}: {e}", path.display());
+                None
+            },
+        };
+
+        // Safety: the file is only written by `save`, under a name tied to the adapter and driver.
+        // With `fallback`, data the driver rejects anyway gives an empty cache instead of an error
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("pipeline_cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+
+        log::info!("Pipeline cache {} {}", path.display(), if data.is_some() { "loaded" } else { "created" });
+        Some(DiskPipelineCache { cache, path })
+    }
+
+    pub fn cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes through a temporary file, so an interrupted save can't leave a truncated cache behind
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(data) = self.cache.get_data() else { return Ok(()) };
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, &data)?;
+        std::fs::rename(&temp, &self.path)?;
+
+        log::info!("Saved pipeline cache to {}", self.path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+//! Chain of full screen effects between the scene and the output. Effects are drawn
+//! one after another, ping-ponging between two intermediate textures
+
+use wgpu::util::DeviceExt;
+
+use std::ops::Range;
+
+use crate::{
+    bloom::{BloomChain, BLOOM_STAGES},
+    graph::{Pass, PassContext},
+    hot_reload::Shader,
+    renderer::{get_render_target, PipelineBuilder, PipelineError, Vertex, QUAD},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EffectKind {
+    /// Sine wave distortion
+    Warp,
+    /// Glow around bright areas, see `bloom`
+    Bloom,
+    /// Gaussian blur
+    Blur,
+    /// Darkened corners
+    Vignette,
+    /// Color channels split towards the edges
+    ChromaticAberration,
+    /// CRT scanlines and screen curvature
+    Scanlines,
+    /// Exposure, contrast, saturation and gamma
+    ColorGrade,
+}
+
+impl EffectKind {
+    pub const ALL: [EffectKind; 7] = [
+        EffectKind::Warp,
+        EffectKind::Bloom,
+        EffectKind::Blur,
+        EffectKind::Vignette,
+        EffectKind::ChromaticAberration,
+        EffectKind::Scanlines,
+        EffectKind::ColorGrade,
+    ];
+
+    /// Matches the `EFFECT_*` constants in `shader_post.wgsl`
+    fn shader_id(self) -> u32 {
+        match self {
+            EffectKind::Warp => 1,
+            EffectKind::Bloom => 2,
+            EffectKind::Blur => 3,
+            EffectKind::Vignette => 4,
+            EffectKind::ChromaticAberration => 5,
+            EffectKind::Scanlines => 6,
+            EffectKind::ColorGrade => 7,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectKind::Warp => "warp",
+            EffectKind::Bloom => "bloom",
+            EffectKind::Blur => "blur",
+            EffectKind::Vignette => "vignette",
+            EffectKind::ChromaticAberration => "chromatic-aberration",
+            EffectKind::Scanlines => "scanlines",
+            EffectKind::ColorGrade => "color-grade",
+        }
+    }
+
+    /// Names of the shader parameters, in the order they are passed to the shader
+    pub fn param_names(self) -> &'static [&'static str] {
+        match self {
+            EffectKind::Warp => &["strength", "speed"],
+            EffectKind::Bloom => &["threshold", "intensity", "radius", "levels"],
+            EffectKind::Blur => &["radius"],
+            EffectKind::Vignette => &["intensity", "radius", "smoothness"],
+            EffectKind::ChromaticAberration => &["offset"],
+            EffectKind::Scanlines => &["intensity", "lines", "curvature"],
+            EffectKind::ColorGrade => &["exposure", "contrast", "saturation", "gamma"],
+        }
+    }
+
+    pub fn default_params(self) -> [f32; 4] {
+        match self {
+            EffectKind::Warp => [1.0, 1.0, 0.0, 0.0],
+            EffectKind::Bloom => [0.6, 1.0, 1.0, 6.0],
+            EffectKind::Blur => [4.0, 0.0, 0.0, 0.0],
+            EffectKind::Vignette => [0.6, 0.4, 0.5, 0.0],
+            EffectKind::ChromaticAberration => [4.0, 0.0, 0.0, 0.0],
+            EffectKind::Scanlines => [0.3, 0.0, 0.05, 0.0],
+            EffectKind::ColorGrade => [0.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn param_index(self, name: &str) -> Option<usize> {
+        self.param_names().iter().position(|p| *p == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub enabled: bool,
+    /// See `EffectKind::param_names`
+    pub params: [f32; 4],
+}
+
+impl Effect {
+    pub fn new(kind: EffectKind) -> Self {
+        Effect { kind, enabled: true, params: kind.default_params() }
+    }
+}
+
+/// `EFFECT.PARAM=VALUE` from the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectParam {
+    pub kind: EffectKind,
+    pub index: usize,
+    pub value: f32,
+}
+
+fn parse_effect_param(s: &str) -> Result<EffectParam, String> {
+    let (name, value) = s.split_once('=').ok_or("parameter must be in <EFFECT>.<PARAM>=<VALUE> format")?;
+    let (effect, param) = name.split_once('.').ok_or("parameter must be in <EFFECT>.<PARAM>=<VALUE> format")?;
+
+    let kind = EffectKind::ALL.into_iter()
+        .find(|k| k.name() == effect)
+        .ok_or_else(|| format!("unknown effect {effect:?}"))?;
+    let index = kind.param_index(param)
+        .ok_or_else(|| format!("{effect} has no parameter {param:?}, expected one of {:?}", kind.param_names()))?;
+
+    let value: f32 = value.parse().map_err(|e| format!("invalid value: {e}"))?;
+    if !value.is_finite() {
+        return Err("value must be finite".into());
+    }
+    if kind == EffectKind::ColorGrade && param == "gamma" && value <= 0.0 {
+        return Err("gamma must be positive".into());
+    }
+
+    Ok(EffectParam { kind, index, value })
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct PostOptions {
+    /// Post-processing effects applied in this order. Pass no value to disable post-processing
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0.., default_value = "warp")]
+    pub post: Vec<EffectKind>,
+
+    /// Effect parameter, e.g. bloom.threshold=0.8. Can be repeated
+    #[arg(long, value_name = "EFFECT.PARAM=VALUE", value_parser = parse_effect_param)]
+    pub post_param: Vec<EffectParam>,
+}
+
+impl Default for PostOptions {
+    fn default() -> Self {
+        PostOptions {
+            post: vec![EffectKind::Warp],
+            post_param: vec![],
+        }
+    }
+}
+
+impl PostOptions {
+    /// Every effect, the requested ones enabled and first in the requested order
+    pub fn effects(&self) -> Vec<Effect> {
+        let mut effects: Vec<Effect> = vec![];
+        for &kind in &self.post {
+            if !effects.iter().any(|e| e.kind == kind) {
+                effects.push(Effect::new(kind));
+            }
+        }
+
+        for kind in EffectKind::ALL {
+            if !effects.iter().any(|e| e.kind == kind) {
+                effects.push(Effect { enabled: false, ..Effect::new(kind) });
+            }
+        }
+
+        for param in &self.post_param {
+            if let Some(effect) = effects.iter_mut().find(|e| e.kind == param.kind) {
+                effect.params[param.index] = param.value;
+            }
+        }
+
+        effects
+    }
+}
+
+/// Per draw uniforms at group 2 of `shader_post.wgsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EffectUniforms {
+    pub kind: u32,
+    /// Non-zero samples the input upside down
+    pub flip_y: u32,
+    pub texel_size: [f32; 2],
+    pub params: [f32; 4],
+}
+
+impl EffectUniforms {
+    pub fn new(effect: &Effect, size: (u32, u32)) -> Self {
+        EffectUniforms {
+            kind: effect.kind.shader_id(),
+            flip_y: 0,
+            texel_size: [1.0 / size.0.max(1) as f32, 1.0 / size.1.max(1) as f32],
+            params: effect.params,
+        }
+    }
+}
+
+/// Layout of `EffectUniforms` at binding 0, addressed with a dynamic offset per draw
+fn effect_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("effect_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<EffectUniforms>() as u64),
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Format of the textures between effects, so values above 1 survive until the output
+pub const POST_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Effect drawn this frame
+struct Draw {
+    /// Uniform slot of the effect, the composite for bloom
+    slot: usize,
+    /// Uniform slots of the bloom mip chain stages
+    bloom: Option<Range<usize>>,
+}
+
+/// Graph pass drawing the enabled effects from its single input into the output
+pub struct PostStack {
+    effects: Vec<Effect>,
+
+    pipeline: wgpu::RenderPipeline,
+    cache: Option<wgpu::PipelineCache>,
+    layouts: [wgpu::BindGroupLayout; 3],
+    bloom: BloomChain,
+
+    buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+
+    effect_buffer: wgpu::Buffer,
+    effect_bind_group: wgpu::BindGroup,
+    effect_stride: u32,
+    /// Slots in `effect_buffer`
+    effect_capacity: usize,
+
+    draws: Vec<Draw>,
+    /// Ping-pong textures, created on first use with more than one effect
+    intermediate: Vec<(wgpu::Texture, wgpu::BindGroup)>,
+    size: (u32, u32),
+}
+
+impl PostStack {
+    /// `uniform_layout` and `texture_layout` are the layouts of the renderer uniforms
+    /// and the input texture, bind groups 0 and 1 of `shader_post.wgsl`
+    pub fn new(
+        device: &wgpu::Device,
+        cache: Option<&wgpu::PipelineCache>,
+        uniform_layout: &wgpu::BindGroupLayout,
+        uniform_bind_group: &wgpu::BindGroup,
+        texture_layout: &wgpu::BindGroupLayout,
+        effects: Vec<Effect>,
+    ) -> Result<Self, PipelineError> {
+        let effect_layout = effect_bind_group_layout(device);
+        let bloom = BloomChain::new(device, cache, &effect_layout)?;
+        let layouts = [uniform_layout.clone(), texture_layout.clone(), effect_layout];
+        let pipeline = post_pipeline(device, cache, &layouts, Shader::Post.builtin())?;
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post vertex buffer"),
+            contents: bytemuck::cast_slice(QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let effect_stride = (std::mem::size_of::<EffectUniforms>() as u32)
+            .next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+
+        // Enough for every effect once, grown in `prepare` if effects are repeated
+        let effect_capacity = EffectKind::ALL.len() + BLOOM_STAGES;
+        let (effect_buffer, effect_bind_group) = effect_buffer(device, &layouts[2], effect_stride, effect_capacity);
+
+        Ok(PostStack {
+            effects, pipeline, layouts, bloom,
+            cache: cache.cloned(),
+            buffer,
+            uniform_bind_group: uniform_bind_group.clone(),
+            effect_buffer, effect_bind_group, effect_stride, effect_capacity,
+            draws: vec![],
+            intermediate: vec![],
+            size: (0, 0),
+        })
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    /// Effects can be toggled, reordered and tweaked freely, changes apply on the next frame
+    pub fn effects_mut(&mut self) -> &mut Vec<Effect> {
+        &mut self.effects
+    }
+
+    pub fn effect_mut(&mut self, kind: EffectKind) -> Option<&mut Effect> {
+        self.effects.iter_mut().find(|e| e.kind == kind)
+    }
+
+    /// Flips the effect on or off, returning whether it is enabled now
+    pub fn toggle(&mut self, kind: EffectKind) -> bool {
+        match self.effect_mut(kind) {
+            Some(effect) => {
+                effect.enabled = !effect.enabled;
+                effect.enabled
+            },
+            None => false,
+        }
+    }
+
+    /// Uniforms of every draw this frame, a plain copy when no effect is enabled
+    fn frame(&self) -> (Vec<EffectUniforms>, Vec<Draw>) {
+        let mut effects: Vec<EffectUniforms> = self.effects.iter()
+            .filter(|e| e.enabled)
+            .map(|e| EffectUniforms::new(e, self.size))
+            .collect();
+
+        if effects.is_empty() {
+            effects.push(EffectUniforms { kind: 0, ..EffectUniforms::new(&Effect::new(EffectKind::Warp), self.size) });
+        }
+
+        // The scene is upside down, the first effect turns it around
+        effects[0].flip_y = 1;
+
+        let mut uniforms = vec![];
+        let mut draws = vec![];
+        for effect in effects {
+            if effect.kind == EffectKind::Bloom.shader_id() {
+                let start = uniforms.len();
+                uniforms.extend(self.bloom.stages(&effect));
+                draws.push(Draw { slot: uniforms.len() - 1, bloom: Some(start..uniforms.len() - 1) });
+            } else {
+                uniforms.push(effect);
+                draws.push(Draw { slot: uniforms.len() - 1, bloom: None });
+            }
+        }
+
+        (uniforms, draws)
+    }
+
+    fn offset(&self, slot: usize) -> u32 {
+        slot as u32 * self.effect_stride
+    }
+}
+
+fn effect_buffer(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, stride: u32, capacity: usize) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Effect uniform buffer"),
+        size: (stride as usize * capacity) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("effect_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<EffectUniforms>() as u64),
+                }),
+            },
+        ],
+    });
+
+    (buffer, bind_group)
+}
+
+/// Draws into `POST_TEXTURE_FORMAT`, the intermediate textures as well as the output of the pass
+fn post_pipeline(
+    device: &wgpu::Device,
+    cache: Option<&wgpu::PipelineCache>,
+    layouts: &[wgpu::BindGroupLayout; 3],
+    source: &str,
+) -> Result<wgpu::RenderPipeline, PipelineError> {
+    PipelineBuilder::new(device, POST_TEXTURE_FORMAT, source)
+        .with_label(Shader::Post.file_name())
+        .with_cache(cache)
+        .with_buffer(Vertex::desc())
+        .with_bind_group(&layouts[0])
+        .with_bind_group(&layouts[1])
+        .with_bind_group(&layouts[2])
+        .build()
+}
+
+impl Pass for PostStack {
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, output_size: (u32, u32)) {
+        if self.size != output_size {
+            self.size = output_size;
+            for (texture, _) in self.intermediate.drain(..) {
+                texture.destroy();
+            }
+        }
+
+        // Bloom effects share one mip chain, sized by the first of them
+        if let Some(bloom) = self.effects.iter().find(|e| e.enabled && e.kind == EffectKind::Bloom) {
+            self.bloom.prepare(device, output_size, bloom.params[3]);
+        }
+
+        let (uniforms, draws) = self.frame();
+        if draws.len() > 1 && self.intermediate.is_empty() {
+            self.intermediate = (0..2)
+                .map(|_| get_render_target(device, "post_texture", POST_TEXTURE_FORMAT, output_size))
+                .collect();
+        }
+
+        if uniforms.len() > self.effect_capacity {
+            self.effect_capacity = uniforms.len();
+            (self.effect_buffer, self.effect_bind_group) = effect_buffer(device, &self.layouts[2], self.effect_stride, self.effect_capacity);
+        }
+
+        let mut data = vec![0; self.effect_stride as usize * uniforms.len()];
+        for (chunk, uniforms) in data.chunks_mut(self.effect_stride as usize).zip(&uniforms) {
+            chunk[..std::mem::size_of::<EffectUniforms>()].copy_from_slice(bytemuck::bytes_of(uniforms));
+        }
+        queue.write_buffer(&self.effect_buffer, 0, &data);
+
+        self.draws = draws;
+    }
+
+    fn record(&self, ctx: &mut PassContext) {
+        for (i, draw) in self.draws.iter().enumerate() {
+            let input = match i {
+                0 => ctx.inputs[0],
+                _ => &self.intermediate[(i - 1) % 2].1,
+            };
+            let is_last = i == self.draws.len() - 1;
+
+            if let Some(stages) = &draw.bloom {
+                let offsets: Vec<u32> = stages.clone().map(|slot| self.offset(slot)).collect();
+                self.bloom.record(ctx.encoder, &self.buffer, input, &self.effect_bind_group, &offsets);
+            }
+
+            let intermediate_view;
+            let mut render_pass = if is_last {
+                ctx.begin_render_pass()
+            } else {
+                intermediate_view = self.intermediate[i % 2].0.create_view(&wgpu::TextureViewDescriptor::default());
+                ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Post effect render pass"),
+                    color_attachments: &[
+                        Some(wgpu::RenderPassColorAttachment {
+                            view: &intermediate_view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        }),
+                    ],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                })
+            };
+
+            if draw.bloom.is_some() {
+                self.bloom.composite(&mut render_pass, &self.buffer, input, &self.effect_bind_group, self.offset(draw.slot));
+                continue;
+            }
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, input, &[]);
+            render_pass.set_bind_group(2, &self.effect_bind_group, &[self.offset(draw.slot)]);
+            render_pass.draw(0..QUAD.len() as u32, 0..1);
+        }
+    }
+
+    fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        if shader == Shader::Post {
+            self.pipeline = post_pipeline(device, self.cache.as_ref(), &self.layouts, source)?;
+        }
+        self.bloom.reload_shader(device, shader, source)
+    }
+}
@@ -0,0 +1,220 @@
+//! Offline recording: a fixed number of frames at a fixed timestep, written as a PNG sequence
+//! or an uncompressed Y4M stream for ffmpeg to encode
+
+use imageproc::image::RgbaImage;
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::headless::{Headless, PendingFrame};
+
+/// Frames rendered ahead of the one being written out
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct RecordOptions {
+    /// Render an animation instead of opening a window, to a Y4M video or to PNG files
+    /// named by a printf-style frame number pattern, like frames/%05d.png
+    #[arg(long, value_name = "OUT.y4m|OUT_%05d.png", value_parser = RecordOutput::parse)]
+    pub record: Option<RecordOutput>,
+
+    /// Number of frames rendered with --record
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..), requires = "record")]
+    pub frames: u32,
+
+    /// Frame rate of the recording, the animation advances 1/FPS seconds per frame
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..), requires = "record")]
+    pub record_fps: u32,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            record: None,
+            frames: 300,
+            record_fps: 60,
+        }
+    }
+}
+
+impl RecordOptions {
+    /// Animation time of frame `index`, counted from `start`
+    pub fn frame_time(&self, start: f32, index: u32) -> f32 {
+        (start as f64 + index as f64 / self.record_fps as f64) as f32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordOutput {
+    /// One file per frame, `{prefix}{frame number padded to width}{suffix}`
+    PngSequence { prefix: String, width: usize, suffix: String },
+    Y4m(PathBuf),
+}
+
+impl RecordOutput {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let path = Path::new(s);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+
+        if extension.eq_ignore_ascii_case("y4m") {
+            return Ok(RecordOutput::Y4m(path.into()));
+        }
+        if !extension.eq_ignore_ascii_case("png") {
+            return Err(format!("recording {s} must be a .y4m or .png file"));
+        }
+
+        // %d or %0<width>d, the way ffmpeg reads image sequences
+        let pattern_error = || format!("PNG sequence {s} needs a frame number pattern like %05d");
+        let (prefix, rest) = s.split_once('%').ok_or_else(pattern_error)?;
+        let (width, suffix) = rest.split_once('d').ok_or_else(pattern_error)?;
+        let width = match width {
+            "" => 0,
+            width if width.starts_with('0') => width.parse().map_err(|_| pattern_error())?,
+            _ => return Err(pattern_error()),
+        };
+        if suffix.contains('%') {
+            return Err(format!("PNG sequence {s} has more than one frame number pattern"));
+        }
+
+        Ok(RecordOutput::PngSequence { prefix: prefix.into(), width, suffix: suffix.into() })
+    }
+
+    /// File of frame `index` in a PNG sequence
+    pub fn frame_path(&self, index: u32) -> Option<PathBuf> {
+        match self {
+            RecordOutput::PngSequence { prefix, width, suffix } => Some(format!("{prefix}{index:0width$}{suffix}").into()),
+            RecordOutput::Y4m(_) => None,
+        }
+    }
+
+    fn writer(&self, size: (u32, u32), fps: u32) -> anyhow::Result<Box<dyn FrameWriter>> {
+        Ok(match self {
+            RecordOutput::PngSequence { .. } => Box::new(PngSequence { output: self.clone(), index: 0 }),
+            RecordOutput::Y4m(path) => {
+                let file = File::create(path).map_err(|e| anyhow::anyhow!("Unable to create {}: {e}", path.display()))?;
+                Box::new(Y4mWriter::new(BufWriter::new(file), size, fps)?)
+            },
+        })
+    }
+}
+
+/// Receives the recorded frames in order
+pub trait FrameWriter {
+    fn write_frame(&mut self, frame: &RgbaImage) -> anyhow::Result<()>;
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct PngSequence {
+    output: RecordOutput,
+    index: u32,
+}
+
+impl FrameWriter for PngSequence {
+    fn write_frame(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        let path = self.output.frame_path(self.index).expect("PNG sequence");
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        frame.save(&path)?;
+        self.index += 1;
+        Ok(())
+    }
+}
+
+/// Uncompressed YUV4MPEG2 stream with full resolution chroma (`C444`), converted with the
+/// BT.601 limited range matrix ffmpeg assumes for Y4M input
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    size: (u32, u32),
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut out: W, size: (u32, u32), fps: u32) -> anyhow::Result<Self> {
+        writeln!(out, "YUV4MPEG2 W{} H{} F{fps}:1 Ip A1:1 C444", size.0, size.1)?;
+        Ok(Y4mWriter { out, size, planes: vec![0; 3 * (size.0 * size.1) as usize] })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Y', Cb and Cr of an sRGB encoded pixel, in the 16-235 / 16-240 limited range
+pub fn rgb_to_ycbcr(rgb: [u8; 3]) -> [u8; 3] {
+    let [r, g, b] = rgb.map(|c| c as f32);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y, cb, cr].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+impl<W: Write> FrameWriter for Y4mWriter<W> {
+    fn write_frame(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            frame.dimensions() == self.size,
+            "Frame of {}x{} in a {}x{} stream", frame.width(), frame.height(), self.size.0, self.size.1,
+        );
+
+        let plane = (self.size.0 * self.size.1) as usize;
+        for (i, pixel) in frame.pixels().enumerate() {
+            let [y, cb, cr] = rgb_to_ycbcr([pixel[0], pixel[1], pixel[2]]);
+            self.planes[i] = y;
+            self.planes[plane + i] = cb;
+            self.planes[2 * plane + i] = cr;
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Renders `options.frames` frames starting at `start` seconds into `writer`. Up to
+/// `FRAMES_IN_FLIGHT` frames are rendered while the oldest one is read back and written
+pub fn record_frames(headless: &mut Headless, options: &RecordOptions, start: f32, writer: &mut dyn FrameWriter) -> anyhow::Result<()> {
+    let mut in_flight: VecDeque<PendingFrame> = VecDeque::with_capacity(FRAMES_IN_FLIGHT);
+    let mut staging = Vec::with_capacity(FRAMES_IN_FLIGHT);
+
+    for index in 0..options.frames {
+        if in_flight.len() == FRAMES_IN_FLIGHT {
+            let pending = in_flight.pop_front().expect("frames in flight");
+            let (frame, buffer) = pending.wait(headless.device())?;
+            writer.write_frame(&frame)?;
+            staging.push(buffer);
+        }
+
+        in_flight.push_back(headless.render_async(options.frame_time(start, index), staging.pop()));
+    }
+
+    for pending in in_flight {
+        let (frame, _) = pending.wait(headless.device())?;
+        writer.write_frame(&frame)?;
+    }
+    writer.finish()
+}
+
+/// Records to the file or files of `options.record`
+pub fn record(headless: &mut Headless, options: &RecordOptions, start: f32) -> anyhow::Result<()> {
+    let Some(output) = &options.record else { return Ok(()) };
+
+    let mut writer = output.writer(headless.size(), options.record_fps)?;
+    let timer = std::time::Instant::now();
+    record_frames(headless, options, start, writer.as_mut())?;
+
+    log::info!("Recorded {} frames in {:.2?}", options.frames, timer.elapsed());
+    Ok(())
+}
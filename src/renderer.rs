@@ -0,0 +1,1115 @@
+use imageproc::image::RgbaImage;
+use wgpu::{naga, util::DeviceExt};
+
+use std::sync::Arc;
+
+use crate::{
+    color::Srgb,
+    font::FontChain,
+    graph::{Attachment, Pass, PassContext, PassDesc, PassId, RenderGraph, TargetDesc},
+    hot_reload::Shader,
+    noise::{validate_noise_shader, volume_bind_group_layout, NoiseMode, NoiseOptions, NoiseVolume},
+    noise_lib::{with_noise_lib, NoiseBasis},
+    overlay::OverlayPass,
+    post::{PostOptions, PostStack, POST_TEXTURE_FORMAT},
+    text::{TextOptions, TextRenderer},
+    tonemap::{TonemapOptions, TonemapPass},
+};
+
+/// Command line options of everything the renderer draws
+#[derive(clap::Args, Clone, Debug)]
+pub struct RenderOptions {
+    #[command(flatten)]
+    pub text: TextOptions,
+
+    #[command(flatten)]
+    pub post: PostOptions,
+
+    #[command(flatten)]
+    pub tonemap: TonemapOptions,
+
+    #[command(flatten)]
+    pub noise: NoiseOptions,
+
+    /// Samples per pixel of the scene, 1 disables multisampling
+    #[arg(long, default_value_t = 1, value_parser = parse_sample_count)]
+    pub msaa: u32,
+
+    /// What the adapter supports for `BACK_TEXTURE_FORMAT`, see `with_adapter`
+    #[arg(skip)]
+    pub scene_format_features: Option<wgpu::TextureFormatFeatures>,
+
+    /// Cache every pipeline is compiled through, see `pipeline_cache::DiskPipelineCache`
+    #[arg(skip)]
+    pub pipeline_cache: Option<wgpu::PipelineCache>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            text: TextOptions::default(),
+            post: PostOptions::default(),
+            tonemap: TonemapOptions::default(),
+            noise: NoiseOptions::default(),
+            msaa: 1,
+            scene_format_features: None,
+            pipeline_cache: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Allows the sample counts `adapter` supports beyond the ones WebGPU guarantees,
+    /// when the device is opened with `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`
+    pub fn with_adapter(mut self, adapter: &wgpu::Adapter) -> Self {
+        self.scene_format_features = Some(adapter.get_texture_format_features(BACK_TEXTURE_FORMAT));
+        self
+    }
+}
+
+fn parse_sample_count(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err(format!("{s} is not one of 1, 2, 4 or 8")),
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Uniforms {
+    pub time: f32,
+    pub aspect: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Colors {
+    pub bg_color: [f32; 3],
+    _pad: u32,
+    pub fg_color: [f32; 3],
+    _pad2: u32,
+}
+
+impl Colors {
+    pub fn new(bg_color: [f32; 3], fg_color: [f32; 3]) -> Self {
+        Colors { bg_color, _pad: 0, fg_color, _pad2: 0 }
+    }
+
+    /// Palette colors are sRGB encoded, the scene is drawn in linear light
+    pub fn to_linear(self) -> Self {
+        Colors::new(Srgb(self.bg_color).to_linear().0, Srgb(self.fg_color).to_linear().0)
+    }
+}
+
+pub const QUAD: &[Vertex] = &[
+    Vertex { pos: [-1.0, -1.0], uv: [0.0, 0.0] },
+    Vertex { pos: [-1.0,  1.0], uv: [0.0, 1.0] },
+    Vertex { pos: [ 1.0, -1.0], uv: [1.0, 0.0] },
+    Vertex { pos: [ 1.0,  1.0], uv: [1.0, 1.0] },
+];
+
+pub const SCENE_SHADER: &str = include_str!("./shader.wgsl");
+pub const TEXT_SHADER: &str = include_str!("./shader_text.wgsl");
+pub const TEXT_SDF_SHADER: &str = include_str!("./shader_text_sdf.wgsl");
+pub const POST_SHADER: &str = include_str!("./shader_post.wgsl");
+pub const BLOOM_SHADER: &str = include_str!("./shader_bloom.wgsl");
+pub const TONEMAP_SHADER: &str = include_str!("./shader_tonemap.wgsl");
+pub const NOISE_SHADER: &str = include_str!("./shader_noise.wgsl");
+pub const NOISE_LIB_SHADER: &str = include_str!("./shader_noise_lib.wgsl");
+
+/// Position of a diagnostic in the shader source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// 1-based line number
+    pub line: u32,
+    /// 1-based column in bytes
+    pub column: u32,
+    /// Byte offset of the start of the span
+    pub offset: u32,
+    /// Length in bytes
+    pub length: u32,
+}
+
+impl From<naga::SourceLocation> for SourceSpan {
+    fn from(loc: naga::SourceLocation) -> Self {
+        SourceSpan {
+            line: loc.line_number,
+            column: loc.line_position,
+            offset: loc.offset,
+            length: loc.length,
+        }
+    }
+}
+
+/// Shader or pipeline that failed to compile
+#[derive(Clone, Debug)]
+pub struct PipelineError {
+    /// Label of the shader, usually its file name
+    pub label: String,
+    /// Where in the source the problem is, if known
+    pub span: Option<SourceSpan>,
+    pub message: String,
+    /// Full multi-line report, quoting the offending source line when there is one
+    pub report: String,
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}:{}:{}: {}", self.label, span.line, span.column, self.message),
+            None => write!(f, "{}: {}", self.label, self.message),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Parses and validates WGSL with naga, before anything is handed to the device
+pub fn validate_shader(label: &str, source: &str) -> Result<naga::Module, PipelineError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| PipelineError {
+        label: label.into(),
+        span: e.location(source).map(SourceSpan::from),
+        message: e.message().into(),
+        report: e.emit_to_string_with_path(source, label),
+    })?;
+
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| PipelineError {
+            label: label.into(),
+            span: e.location(source).map(SourceSpan::from),
+            message: e.as_inner().to_string(),
+            report: e.emit_to_string_with_path(source, label),
+        })?;
+
+    Ok(module)
+}
+
+/// Runs `create` inside a validation error scope, so mistakes come back as a `PipelineError`
+/// instead of reaching the uncaptured error handler
+pub fn capture_pipeline_error<T>(device: &wgpu::Device, label: &str, create: impl FnOnce() -> T) -> Result<T, PipelineError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let pipeline = create();
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(e) => Err(PipelineError {
+            label: label.into(),
+            span: None,
+            message: e.to_string(),
+            report: e.to_string(),
+        }),
+        None => Ok(pipeline),
+    }
+}
+
+/// Samples per pixel of a color target, with the features of its format they are checked against
+#[derive(Clone, Copy, Debug)]
+pub struct Multisample {
+    pub count: u32,
+    pub format_features: wgpu::TextureFormatFeatures,
+}
+
+impl Multisample {
+    /// `count` samples of `format`. Without `adapter_features`, or if `device` wasn't opened with
+    /// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`, only what WebGPU guarantees is allowed
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        count: u32,
+        adapter_features: Option<wgpu::TextureFormatFeatures>,
+    ) -> Self {
+        let format_features = match adapter_features {
+            Some(features) if device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) => features,
+            _ => format.guaranteed_format_features(device.features()),
+        };
+        Multisample { count, format_features }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.format_features.flags.sample_count_supported(self.count)
+    }
+
+    pub fn supported_counts(&self) -> Vec<u32> {
+        [1, 2, 4, 8, 16].into_iter().filter(|&n| self.format_features.flags.sample_count_supported(n)).collect()
+    }
+}
+
+/// Render pipeline with sensible defaults: a triangle strip without culling or depth, `vs_main` and
+/// `fs_main` taken from the same module, and a single color target without blending
+pub struct PipelineBuilder<'a> {
+    device: &'a wgpu::Device,
+    label: &'a str,
+    bind_groups: Vec<&'a wgpu::BindGroupLayout>,
+    push_constant_ranges: Vec<wgpu::PushConstantRange>,
+    blending: Option<wgpu::BlendState>,
+    buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    shader_code: &'a str,
+    /// Label and source of the fragment stage, when it isn't in `shader_code`
+    fragment_shader: Option<(&'a str, &'a str)>,
+    vertex_entry_point: &'a str,
+    fragment_entry_point: &'a str,
+    constants: Vec<(&'a str, f64)>,
+    color_format: wgpu::TextureFormat,
+    extra_targets: Vec<Option<wgpu::ColorTargetState>>,
+    topology: wgpu::PrimitiveTopology,
+    front_face: wgpu::FrontFace,
+    cull_mode: Option<wgpu::Face>,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: Option<Multisample>,
+    cache: Option<&'a wgpu::PipelineCache>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new(device: &'a wgpu::Device, color_format: wgpu::TextureFormat, shader_code: &'a str) -> Self {
+        PipelineBuilder {
+            color_format,
+            device,
+            label: "shader",
+            bind_groups: vec![],
+            push_constant_ranges: vec![],
+            buffers: vec![],
+            shader_code,
+            fragment_shader: None,
+            vertex_entry_point: "vs_main",
+            fragment_entry_point: "fs_main",
+            constants: vec![],
+            blending: None,
+            extra_targets: vec![],
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            depth_stencil: None,
+            multisample: None,
+            cache: None,
+        }
+    }
+
+    /// Name used for the shader in errors and graphics debuggers
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Blending of the first color target
+    pub fn with_blending(mut self, blending: wgpu::BlendState) -> Self {
+        self.blending = Some(blending);
+        self
+    }
+
+    pub fn with_bind_group(mut self, bind_group: &'a wgpu::BindGroupLayout) -> Self {
+        self.bind_groups.push(bind_group);
+        self
+    }
+
+    /// Needs `Features::PUSH_CONSTANTS` on the device
+    pub fn with_push_constants(mut self, stages: wgpu::ShaderStages, range: std::ops::Range<u32>) -> Self {
+        self.push_constant_ranges.push(wgpu::PushConstantRange { stages, range });
+        self
+    }
+
+    pub fn with_buffer(mut self, buffer: wgpu::VertexBufferLayout<'a>) -> Self {
+        self.buffers.push(buffer);
+        self
+    }
+
+    /// Takes the fragment stage from its own module, the one passed to `new` then only needs the vertex stage
+    pub fn with_fragment_shader(mut self, label: &'a str, shader_code: &'a str) -> Self {
+        self.fragment_shader = Some((label, shader_code));
+        self
+    }
+
+    pub fn with_entry_points(mut self, vertex: &'a str, fragment: &'a str) -> Self {
+        self.vertex_entry_point = vertex;
+        self.fragment_entry_point = fragment;
+        self
+    }
+
+    /// Value of a pipeline-overridable constant, given to both stages
+    pub fn with_constant(mut self, name: &'a str, value: f64) -> Self {
+        self.constants.push((name, value));
+        self
+    }
+
+    /// Adds a color target after the first one, at the next `@location`
+    pub fn with_color_target(mut self, format: wgpu::TextureFormat, blending: Option<wgpu::BlendState>) -> Self {
+        self.extra_targets.push(Some(wgpu::ColorTargetState {
+            format,
+            blend: blending,
+            write_mask: wgpu::ColorWrites::ALL,
+        }));
+        self
+    }
+
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Culls the triangles facing `face`, `front_face` being the winding of the front ones
+    pub fn with_culling(mut self, front_face: wgpu::FrontFace, face: wgpu::Face) -> Self {
+        self.front_face = front_face;
+        self.cull_mode = Some(face);
+        self
+    }
+
+    pub fn with_depth_stencil(mut self, depth_stencil: wgpu::DepthStencilState) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
+    /// Draws into a multisampled target, `multisample` has to describe the first color format
+    pub fn with_multisample(mut self, multisample: Multisample) -> Self {
+        self.multisample = Some(multisample);
+        self
+    }
+
+    /// Compiles through `cache` when there is one, see `pipeline_cache`
+    pub fn with_cache(mut self, cache: Option<&'a wgpu::PipelineCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.multisample.map_or(1, |m| m.count)
+    }
+
+    /// Validates the shaders with naga and the sample count against the format features, then
+    /// creates the pipeline through `capture_pipeline_error`
+    pub fn build(self) -> Result<wgpu::RenderPipeline, PipelineError> {
+        validate_shader(self.label, self.shader_code)?;
+        if let Some((label, shader_code)) = self.fragment_shader {
+            validate_shader(label, shader_code)?;
+        }
+
+        if let Some(multisample) = self.multisample.filter(|m| !m.is_supported()) {
+            let message = format!(
+                "{} samples per pixel are not supported for {:?}, supported counts are {:?}",
+                multisample.count, self.color_format, multisample.supported_counts(),
+            );
+            return Err(PipelineError {
+                label: self.label.into(),
+                span: None,
+                report: message.clone(),
+                message,
+            });
+        }
+
+        capture_pipeline_error(self.device, self.label, || self.create_pipeline())
+    }
+
+    fn create_pipeline(&self) -> wgpu::RenderPipeline {
+        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(self.label),
+            source: wgpu::ShaderSource::Wgsl(self.shader_code.into()),
+        });
+
+        let fragment_shader = self.fragment_shader.map(|(label, shader_code)| {
+            self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+            })
+        });
+
+        let pipeline_layout = self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("some render pipeline layout"),
+            bind_group_layouts: self.bind_groups.as_slice(),
+            push_constant_ranges: self.push_constant_ranges.as_slice(),
+        });
+
+        let mut targets = vec![
+            Some(wgpu::ColorTargetState {
+                format: self.color_format,
+                blend: self.blending,
+                write_mask: wgpu::ColorWrites::ALL,
+            }),
+        ];
+        targets.extend(self.extra_targets.iter().cloned());
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: self.constants.as_slice(),
+            ..Default::default()
+        };
+
+        self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some(self.vertex_entry_point),
+                buffers: self.buffers.as_slice(),
+                compilation_options: compilation_options.clone(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: fragment_shader.as_ref().unwrap_or(&shader),
+                entry_point: Some(self.fragment_entry_point),
+                targets: targets.as_slice(),
+                compilation_options,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: self.topology,
+                strip_index_format: None,
+                front_face: self.front_face,
+                cull_mode: self.cull_mode,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: self.cache,
+        })
+    }
+}
+
+/// Layout of a filterable 2D texture at binding 0 and its sampler at binding 1
+pub fn texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("texture_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Layout of `Uniforms` at binding 0 and `Colors` at binding 1
+pub fn uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("uniform_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Inverts whatever is already drawn under the text
+pub fn text_blend_state() -> wgpu::BlendState {
+    let text_blending = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::OneMinusDst,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        ..Default::default()
+    };
+
+    wgpu::BlendState {
+        color: text_blending,
+        alpha: text_blending,
+    }
+}
+
+/// Uploads `img` into a sampleable `Rgba8Unorm` texture
+pub fn create_image_texture(device: &wgpu::Device, queue: &wgpu::Queue, label: &str, img: &RgbaImage) -> wgpu::Texture {
+    let (width, height) = img.dimensions();
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+
+    texture
+}
+
+/// Texture that can be drawn into and sampled through `texture_bind_group_layout`
+pub fn get_render_target(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    size: (u32, u32),
+) -> (wgpu::Texture, wgpu::BindGroup) {
+    let texture_size = wgpu::Extent3d {
+        width: size.0,
+        height: size.1,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some(label),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let text_texture_sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor {
+        label: Some("text_texture_sampler"),
+        address_mode_u: wgpu::AddressMode::MirrorRepeat,
+        address_mode_v: wgpu::AddressMode::MirrorRepeat,
+        address_mode_w: wgpu::AddressMode::MirrorRepeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let group_layout = texture_bind_group_layout(device);
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("{label}_bind_group")),
+        layout: &group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&text_texture_sampler),
+            },
+        ],
+    });
+
+    (texture, bind_group)
+}
+
+/// The scene is drawn in linear light, with room above 1 for the post effects to work with
+pub const BACK_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// What the scene pipelines are built from
+#[derive(Clone)]
+struct SceneShader {
+    source: String,
+    noise_lib: String,
+    /// Compute shader filling the noise volume
+    noise: String,
+    basis: NoiseBasis,
+}
+
+/// `volume` is the noise volume layout and period, for the pipeline sampling it instead of evaluating the noise
+fn scene_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    multisample: Multisample,
+    cache: Option<&wgpu::PipelineCache>,
+    uniform_layout: &wgpu::BindGroupLayout,
+    volume: Option<(&wgpu::BindGroupLayout, f32)>,
+    shader: &SceneShader,
+) -> Result<wgpu::RenderPipeline, PipelineError> {
+    let source = with_noise_lib(&shader.source, &shader.noise_lib);
+    let builder = PipelineBuilder::new(device, format, &source)
+        .with_label(Shader::Scene.file_name())
+        .with_multisample(multisample)
+        .with_cache(cache)
+        .with_buffer(Vertex::desc())
+        .with_bind_group(uniform_layout)
+        .with_constant("NOISE_BASIS", shader.basis.shader_id() as f64);
+
+    match volume {
+        Some((volume_layout, period)) => builder
+            .with_bind_group(volume_layout)
+            .with_entry_points("vs_main", "fs_volume")
+            .with_constant("NOISE_PERIOD", period as f64)
+            .build(),
+        None => builder.build(),
+    }
+}
+
+/// Noise background
+struct ScenePass {
+    pipeline: wgpu::RenderPipeline,
+    format: wgpu::TextureFormat,
+    multisample: Multisample,
+    cache: Option<wgpu::PipelineCache>,
+    /// Last sources that compiled, for the volume pipeline when it is created later
+    shader: SceneShader,
+    buffer: wgpu::Buffer,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    uniform_bind_group: wgpu::BindGroup,
+
+    noise: NoiseOptions,
+    volume_layout: wgpu::BindGroupLayout,
+    /// Pipeline sampling the volume and the volume, created the first time `NoiseMode::Volume` is used
+    volume: Option<(wgpu::RenderPipeline, NoiseVolume)>,
+}
+
+impl ScenePass {
+    fn pipeline(&self, device: &wgpu::Device, volume: Option<&NoiseVolume>, shader: &SceneShader) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let layout = volume.map(|volume| (&self.volume_layout, volume.period()));
+        scene_pipeline(device, self.format, self.multisample, self.cache.as_ref(), &self.uniform_bind_group_layout, layout, shader)
+    }
+
+    fn set_noise_mode(&mut self, device: &wgpu::Device, mode: NoiseMode) -> Result<(), PipelineError> {
+        if mode == NoiseMode::Volume && self.volume.is_none() {
+            let volume = NoiseVolume::new(
+                device, &self.volume_layout,
+                self.noise.noise_volume_size, self.shader.basis, &self.shader.noise, &self.shader.noise_lib,
+                self.cache.as_ref(),
+            )?;
+            let pipeline = self.pipeline(device, Some(&volume), &self.shader)?;
+            self.volume = Some((pipeline, volume));
+        }
+
+        self.noise.noise = mode;
+        Ok(())
+    }
+}
+
+impl Pass for ScenePass {
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, _output_size: (u32, u32)) {
+        if self.noise.noise == NoiseMode::Volume && let Some((_, volume)) = &mut self.volume {
+            volume.prepare(device, queue);
+        }
+    }
+
+    fn record(&self, ctx: &mut PassContext) {
+        let mut render_pass = ctx.begin_render_pass();
+
+        match &self.volume {
+            Some((pipeline, volume)) if self.noise.noise == NoiseMode::Volume => {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(1, volume.bind_group(), &[]);
+            },
+            _ => render_pass.set_pipeline(&self.pipeline),
+        }
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..QUAD.len() as u32, 0..1);
+    }
+
+    fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        let scene_shader = match shader {
+            Shader::Scene => SceneShader { source: source.into(), ..self.shader.clone() },
+            Shader::NoiseLib => SceneShader { noise_lib: source.into(), ..self.shader.clone() },
+            Shader::Noise => {
+                match &mut self.volume {
+                    Some((_, volume)) => volume.reload_shader(device, shader, source)?,
+                    // Kept for when the volume is created, which shouldn't fail on it then
+                    None => validate_noise_shader(source, &self.shader.noise_lib)?,
+                }
+                self.shader.noise = source.into();
+                return Ok(());
+            },
+            _ => return Ok(()),
+        };
+
+        // Both pipelines or neither, so the two modes always draw the same source
+        let pipeline = self.pipeline(device, None, &scene_shader)?;
+        let volume_pipeline = match &self.volume {
+            Some((_, volume)) => Some(self.pipeline(device, Some(volume), &scene_shader)?),
+            None => None,
+        };
+        if shader == Shader::NoiseLib && let Some((_, volume)) = &mut self.volume {
+            volume.reload_shader(device, shader, source)?;
+        }
+
+        self.pipeline = pipeline;
+        if let (Some(volume_pipeline), Some((current, _))) = (volume_pipeline, &mut self.volume) {
+            *current = volume_pipeline;
+        }
+        self.shader = scene_shader;
+        Ok(())
+    }
+}
+
+/// Text drawn over the scene, before the post effects warp it
+struct TextPass {
+    text: TextRenderer,
+}
+
+impl Pass for TextPass {
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, output_size: (u32, u32)) {
+        self.text.prepare(device, queue, output_size);
+    }
+
+    fn record(&self, ctx: &mut PassContext) {
+        let mut render_pass = ctx.begin_render_pass();
+        self.text.draw(&mut render_pass);
+    }
+
+    fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        self.text.reload_shader(device, shader, source)
+    }
+}
+
+/// Everything needed to draw a frame, independent of where the frame ends up
+/// (window surface or offscreen texture)
+pub struct Renderer {
+    graph: RenderGraph,
+    scene: PassId,
+    text: PassId,
+    post: PassId,
+    tonemap: PassId,
+    /// Created the first time it is shown
+    overlay: Option<PassId>,
+    output_format: wgpu::TextureFormat,
+    cache: Option<wgpu::PipelineCache>,
+
+    uniform_buffer: wgpu::Buffer,
+    _color_buffer: wgpu::Buffer,
+}
+
+impl Renderer {
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        size: (u32, u32),
+        colors: Colors,
+        options: &RenderOptions,
+    ) -> anyhow::Result<Self> {
+        // Load vertex buffer
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex buffer"),
+            contents: bytemuck::cast_slice(QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        // Load uniform buffers
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform buffer"),
+            contents: bytemuck::cast_slice(&[Uniforms {
+                time: 0.0,
+                aspect: size.0 as f32 / size.1 as f32,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color buffer"),
+            contents: bytemuck::cast_slice(&[colors.to_linear()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group_layout = uniform_bind_group_layout(device);
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("uniform_bind_group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: color_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Build the pass graph
+
+        let mut graph = RenderGraph::new(size);
+
+        // The pipelines check the sample count, so they are built before the target is created with it
+        let multisample = Multisample::new(device, BACK_TEXTURE_FORMAT, options.msaa, options.scene_format_features);
+        let cache = options.pipeline_cache.as_ref();
+        let scene_shader = SceneShader {
+            source: SCENE_SHADER.into(),
+            noise_lib: NOISE_LIB_SHADER.into(),
+            noise: Shader::Noise.builtin().into(),
+            basis: options.noise.noise_basis,
+        };
+        let mut scene_pass = ScenePass {
+            pipeline: scene_pipeline(device, BACK_TEXTURE_FORMAT, multisample, cache, &uniform_bind_group_layout, None, &scene_shader)?,
+            format: BACK_TEXTURE_FORMAT,
+            multisample,
+            cache: cache.cloned(),
+            shader: scene_shader,
+            buffer,
+            uniform_bind_group_layout: uniform_bind_group_layout.clone(),
+            uniform_bind_group: uniform_bind_group.clone(),
+            noise: options.noise,
+            volume_layout: volume_bind_group_layout(device),
+            volume: None,
+        };
+        scene_pass.set_noise_mode(device, options.noise.noise)?;
+
+        let back = graph.add_target(device, TargetDesc {
+            label: "back_texture".into(),
+            format: BACK_TEXTURE_FORMAT,
+            scale: 1.0,
+            sample_count: multisample.count,
+        });
+
+        let scene = graph.add_pass(
+            PassDesc {
+                label: "Scene render pass".into(),
+                inputs: vec![],
+                output: Attachment::Target(back),
+                clear: Some(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
+            },
+            scene_pass,
+        )?;
+
+        let text = graph.add_pass(
+            PassDesc {
+                label: "Text render pass".into(),
+                inputs: vec![],
+                output: Attachment::Target(back),
+                clear: None,
+            },
+            TextPass {
+                text: TextRenderer::new(
+                    device, BACK_TEXTURE_FORMAT, multisample, cache,
+                    Arc::new(FontChain::load(&options.text.font)?),
+                    &options.text,
+                )?,
+            },
+        )?;
+
+        let texture_bind_group_layout = texture_bind_group_layout(device);
+
+        let post_texture = graph.add_target(device, TargetDesc {
+            label: "post_texture".into(),
+            format: POST_TEXTURE_FORMAT,
+            scale: 1.0,
+            sample_count: 1,
+        });
+
+        let post = graph.add_pass(
+            PassDesc {
+                label: "Post render pass".into(),
+                inputs: vec![back],
+                output: Attachment::Target(post_texture),
+                clear: Some(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
+            },
+            PostStack::new(
+                device, cache,
+                &uniform_bind_group_layout, &uniform_bind_group,
+                &texture_bind_group_layout,
+                options.post.effects(),
+            )?,
+        )?;
+
+        let tonemap = graph.add_pass(
+            PassDesc {
+                label: "Tonemap render pass".into(),
+                inputs: vec![post_texture],
+                output: Attachment::Output,
+                clear: None,
+            },
+            TonemapPass::new(device, output_format, cache, options.tonemap)?,
+        )?;
+
+        Ok(Renderer {
+            graph, scene, text, post, tonemap,
+            overlay: None,
+            output_format,
+            cache: cache.cloned(),
+            uniform_buffer,
+            _color_buffer: color_buffer,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.graph.size()
+    }
+
+    fn text_renderer(&self) -> &TextRenderer {
+        &self.graph.pass::<TextPass>(self.text).expect("text pass").text
+    }
+
+    pub fn text(&self) -> &str {
+        self.text_renderer().text()
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if let Some(pass) = self.graph.pass_mut::<TextPass>(self.text) {
+            pass.text.set_text(text);
+        }
+    }
+
+    pub fn noise_mode(&self) -> NoiseMode {
+        self.graph.pass::<ScenePass>(self.scene).expect("scene pass").noise.noise
+    }
+
+    /// Switches how the background noise is evaluated. The noise volume is created and
+    /// filled the first time it is used, on error the current mode is kept
+    pub fn set_noise_mode(&mut self, device: &wgpu::Device, mode: NoiseMode) -> Result<(), PipelineError> {
+        self.graph.pass_mut::<ScenePass>(self.scene).expect("scene pass").set_noise_mode(device, mode)
+    }
+
+    pub fn post(&self) -> &PostStack {
+        self.graph.pass(self.post).expect("post pass")
+    }
+
+    /// Post-processing effects, can be changed between frames
+    pub fn post_mut(&mut self) -> &mut PostStack {
+        self.graph.pass_mut(self.post).expect("post pass")
+    }
+
+    pub fn tonemap(&self) -> &TonemapPass {
+        self.graph.pass(self.tonemap).expect("tonemap pass")
+    }
+
+    pub fn tonemap_mut(&mut self) -> &mut TonemapPass {
+        self.graph.pass_mut(self.tonemap).expect("tonemap pass")
+    }
+
+    pub fn overlay_visible(&self) -> bool {
+        self.overlay
+            .and_then(|id| self.graph.pass::<OverlayPass>(id))
+            .is_some_and(|overlay| overlay.is_visible())
+    }
+
+    /// Shows or hides the performance overlay, creating it the first time it is shown.
+    /// The GPU time of every pass is measured while it is visible
+    pub fn set_overlay_visible(&mut self, device: &wgpu::Device, visible: bool) -> anyhow::Result<()> {
+        let id = match self.overlay {
+            Some(id) => id,
+            None if !visible => return Ok(()),
+            None => {
+                // Loading the system fonts again would stall the frame the overlay is first shown on
+                let fonts = self.text_renderer().fonts().clone();
+                let overlay = OverlayPass::new(device, self.output_format, self.cache.as_ref(), fonts)?;
+                let id = self.graph.add_pass(
+                    PassDesc {
+                        label: "Overlay render pass".into(),
+                        inputs: vec![],
+                        output: Attachment::Output,
+                        clear: None,
+                    },
+                    overlay,
+                )?;
+                self.overlay = Some(id);
+                id
+            },
+        };
+
+        self.graph.pass_mut::<OverlayPass>(id).expect("overlay pass").set_visible(visible);
+        self.graph.set_timing(visible);
+        Ok(())
+    }
+
+    pub fn set_overlay_text(&mut self, text: &str) {
+        if let Some(overlay) = self.overlay.and_then(|id| self.graph.pass_mut::<OverlayPass>(id)) {
+            overlay.set_text(text);
+        }
+    }
+
+    /// GPU time of every pass of a recent frame while the overlay is visible, `None` before the first
+    /// one is read back or without `Features::TIMESTAMP_QUERY`
+    pub fn pass_timings(&self) -> Option<Vec<(&str, std::time::Duration)>> {
+        self.graph.pass_timings()
+    }
+
+    /// Call after submitting the encoder given to `render`
+    pub fn frame_submitted(&mut self) {
+        self.graph.frame_submitted();
+    }
+
+    /// Passes in the order they are recorded
+    pub fn pass_order(&self) -> Vec<&str> {
+        self.graph.pass_order()
+    }
+
+    /// Rebuilds the pipelines using `shader` from `source`. On error the previous pipelines are kept
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        self.graph.reload_shader(device, shader, source)
+    }
+
+    pub fn on_resize(&mut self, device: &wgpu::Device, w: u32, h: u32) {
+        self.graph.resize(device, (w, h));
+    }
+
+    /// Records every pass into `encoder`, with `target` receiving the final image
+    pub fn render(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, time: f32) {
+        let size = self.size();
+
+        // Update uniform buffer
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Uniforms {
+            time,
+            aspect: size.0 as f32 / size.1 as f32,
+        }]));
+
+        self.graph.prepare(device, queue);
+        self.graph.execute(encoder, target);
+    }
+}
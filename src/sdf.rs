@@ -0,0 +1,303 @@
+//! Signed distance fields for glyphs, so a glyph rasterized once at `SDF_SIZE`
+//! stays crisp at any size and can get outlines, shadows and glow in the shader
+
+use crate::{color::Srgb, palette::parse_color_arg};
+
+/// Font size glyphs are rasterized at before being converted to distance fields
+pub const SDF_SIZE: f32 = 64.0;
+
+/// Distance in pixels (at `SDF_SIZE`) covered by the field on each side of the glyph edge.
+/// Glyph bitmaps are padded by this much, it also limits outline, glow and shadow sizes
+pub const SDF_SPREAD: u32 = 8;
+
+/// 1D squared euclidean distance transform (Felzenszwalb & Huttenlocher).
+/// `f` holds 0 for feature pixels and `f32::INFINITY` elsewhere
+fn edt_1d(f: &[f32], out: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32
+    };
+
+    for (q, &fq) in f.iter().enumerate().skip(1) {
+        if fq == f32::INFINITY {
+            continue;
+        }
+
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+
+        if s <= z[k] {
+            // Only reachable with k == 0, when no feature was found before q
+            v[0] = q;
+            z[0] = f32::NEG_INFINITY;
+            z[1] = f32::INFINITY;
+            k = 0;
+            continue;
+        }
+
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    // Rows without any feature pixel stay infinitely far away
+    if f[v[0]] == f32::INFINITY {
+        out.fill(f32::INFINITY);
+        return;
+    }
+
+    k = 0;
+    for (q, out) in out.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let d = q as f32 - v[k] as f32;
+        *out = d * d + f[v[k]];
+    }
+}
+
+/// Squared distance of every pixel to the nearest pixel where `is_feature` is true
+fn edt_2d(w: usize, h: usize, is_feature: impl Fn(usize) -> bool) -> Vec<f32> {
+    let mut grid: Vec<f32> = (0..w * h)
+        .map(|i| if is_feature(i) { 0.0 } else { f32::INFINITY })
+        .collect();
+
+    let n = w.max(h);
+    let mut f = vec![0.0; n];
+    let mut out = vec![0.0; n];
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+
+    for x in 0..w {
+        for y in 0..h {
+            f[y] = grid[y * w + x];
+        }
+        edt_1d(&f[..h], &mut out[..h], &mut v, &mut z);
+        for y in 0..h {
+            grid[y * w + x] = out[y];
+        }
+    }
+
+    for y in 0..h {
+        f[..w].copy_from_slice(&grid[y * w..(y + 1) * w]);
+        edt_1d(&f[..w], &mut out[..w], &mut v, &mut z);
+        grid[y * w..(y + 1) * w].copy_from_slice(&out[..w]);
+    }
+
+    grid
+}
+
+/// Converts a coverage bitmap into a distance field of the same size.
+/// 0.5 (128) lies on the glyph edge, values grow inwards and reach 0 or 1
+/// `spread` pixels away from the edge
+pub fn generate_sdf(coverage: &[u8], w: u32, h: u32, spread: u32) -> Vec<u8> {
+    let (w, h) = (w as usize, h as usize);
+    assert_eq!(coverage.len(), w * h, "Coverage bitmap size mismatch");
+
+    let inside = |i: usize| coverage[i] >= 128;
+    let to_outside = edt_2d(w, h, |i| !inside(i));
+    let to_inside = edt_2d(w, h, inside);
+
+    (0..w * h)
+        .map(|i| {
+            // Distances are measured between pixel centers, the edge lies half a pixel away
+            let distance = if inside(i) {
+                to_outside[i].sqrt() - 0.5
+            } else {
+                -(to_inside[i].sqrt() - 0.5)
+            };
+
+            let value = 0.5 + distance / (2.0 * spread as f32);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Shader parameters of the SDF text pipeline. Colors are straight (not premultiplied)
+/// RGBA, the layers are blended over the scene as shadow, glow, outline, fill
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SdfParams {
+    pub fill_color: [f32; 4],
+    pub outline_color: [f32; 4],
+    pub shadow_color: [f32; 4],
+    pub glow_color: [f32; 4],
+    /// Shadow offset in screen pixels, y pointing down
+    pub shadow_offset: [f32; 2],
+    /// Outline width in screen pixels
+    pub outline_width: f32,
+    /// Glow radius in screen pixels
+    pub glow_width: f32,
+    /// Width of the shadow's soft edge in screen pixels
+    pub shadow_softness: f32,
+    /// Screen pixels per distance field unit, set by the text renderer
+    pub distance_scale: f32,
+    /// Pads the struct to the 16 byte alignment of WGSL uniforms
+    pub _pad: [f32; 2],
+}
+
+impl Default for SdfParams {
+    fn default() -> Self {
+        SdfParams {
+            fill_color: [1.0, 1.0, 1.0, 1.0],
+            outline_color: [0.0, 0.0, 0.0, 0.0],
+            shadow_color: [0.0, 0.0, 0.0, 0.0],
+            glow_color: [0.0, 0.0, 0.0, 0.0],
+            shadow_offset: [0.0, 0.0],
+            outline_width: 0.0,
+            glow_width: 0.0,
+            shadow_softness: 1.0,
+            distance_scale: 1.0,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+impl SdfParams {
+    /// Largest outline, glow or shadow extent that still fits into the glyph padding
+    pub fn max_effect_width(font_size: f32) -> f32 {
+        SDF_SPREAD as f32 * font_size / SDF_SIZE
+    }
+
+    /// Clamps the effect sizes to what the distance field can represent at `font_size`
+    pub fn clamped(mut self, font_size: f32) -> Self {
+        let max = Self::max_effect_width(font_size);
+        self.distance_scale = 2.0 * max;
+        self.outline_width = self.outline_width.clamp(0.0, max);
+        self.glow_width = self.glow_width.clamp(0.0, max);
+        self.shadow_softness = self.shadow_softness.clamp(0.0, max);
+
+        let offset = (self.shadow_offset[0].powi(2) + self.shadow_offset[1].powi(2)).sqrt();
+        if offset > max {
+            self.shadow_offset = self.shadow_offset.map(|o| o * max / offset);
+        }
+
+        self
+    }
+}
+
+/// Layout of `SdfParams` at binding 0
+pub fn sdf_params_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("sdf_params_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Text effects of `--text-mode sdf`. Sizes are in screen pixels and are limited
+/// to `SDF_SPREAD` pixels at `SDF_SIZE`, scaled with the font size
+#[derive(clap::Args, Clone, Debug)]
+pub struct SdfOptions {
+    /// Text fill color, as #rrggbb or hsl(h, s%, l%)
+    #[arg(long, value_name = "COLOR", default_value = "#ffffff", value_parser = parse_color_arg)]
+    pub text_color: [f32; 3],
+
+    /// Outline width in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 0.0, value_parser = parse_effect_width)]
+    pub text_outline: f32,
+
+    #[arg(long, value_name = "COLOR", default_value = "#000000", value_parser = parse_color_arg)]
+    pub text_outline_color: [f32; 3],
+
+    /// Drop shadow offset in pixels, y pointing down
+    #[arg(long, value_name = "DX,DY", value_parser = parse_offset)]
+    pub text_shadow: Option<[f32; 2]>,
+
+    #[arg(long, value_name = "COLOR", default_value = "#000000", value_parser = parse_color_arg)]
+    pub text_shadow_color: [f32; 3],
+
+    /// Width of the shadow's soft edge in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 2.0, value_parser = parse_effect_width)]
+    pub text_shadow_softness: f32,
+
+    /// Glow radius in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 0.0, value_parser = parse_effect_width)]
+    pub text_glow: f32,
+
+    #[arg(long, value_name = "COLOR", default_value = "#ffffff", value_parser = parse_color_arg)]
+    pub text_glow_color: [f32; 3],
+}
+
+impl Default for SdfOptions {
+    fn default() -> Self {
+        SdfOptions {
+            text_color: [1.0, 1.0, 1.0],
+            text_outline: 0.0,
+            text_outline_color: [0.0, 0.0, 0.0],
+            text_shadow: None,
+            text_shadow_color: [0.0, 0.0, 0.0],
+            text_shadow_softness: 2.0,
+            text_glow: 0.0,
+            text_glow_color: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+fn parse_effect_width(s: &str) -> Result<f32, String> {
+    let width: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(width >= 0.0 && width.is_finite()) {
+        return Err("effect width must be a non-negative number of pixels".into());
+    }
+    Ok(width)
+}
+
+fn parse_offset(s: &str) -> Result<[f32; 2], String> {
+    let (x, y) = s.split_once(',').ok_or("offset must be in <DX>,<DY> format")?;
+    let x: f32 = x.trim().parse().map_err(|e| format!("invalid x offset: {e}"))?;
+    let y: f32 = y.trim().parse().map_err(|e| format!("invalid y offset: {e}"))?;
+
+    if !x.is_finite() || !y.is_finite() {
+        return Err("offset must be finite".into());
+    }
+    Ok([x, y])
+}
+
+impl SdfOptions {
+    /// Whether any effect beyond the plain fill is enabled
+    pub fn has_effects(&self) -> bool {
+        self.text_outline > 0.0 || self.text_shadow.is_some() || self.text_glow > 0.0
+    }
+
+    /// The colors are given in sRGB, the scene is drawn in linear light
+    pub fn params(&self) -> SdfParams {
+        let opaque = |color: [f32; 3]| {
+            let [r, g, b] = Srgb(color).to_linear().0;
+            [r, g, b, 1.0]
+        };
+        let transparent = [0.0; 4];
+
+        SdfParams {
+            fill_color: opaque(self.text_color),
+            outline_color: if self.text_outline > 0.0 { opaque(self.text_outline_color) } else { transparent },
+            shadow_color: if self.text_shadow.is_some() { opaque(self.text_shadow_color) } else { transparent },
+            glow_color: if self.text_glow > 0.0 { opaque(self.text_glow_color) } else { transparent },
+            shadow_offset: self.text_shadow.unwrap_or_default(),
+            outline_width: self.text_outline,
+            glow_width: self.text_glow,
+            shadow_softness: self.text_shadow_softness,
+            ..Default::default()
+        }
+    }
+}
@@ -25,6 +25,16 @@ var<uniform> u: Uniforms;
 @group(0) @binding(1)
 var<uniform> c: Colors;
 
+// Only used by `fs_volume`, filled by shader_noise.wgsl
+@group(1) @binding(0)
+var noise_volume: texture_3d<f32>;
+
+@group(1) @binding(1)
+var noise_sampler: sampler;
+
+// Units of noise one repetition of the volume covers, PERIOD in shader_noise.wgsl
+override NOISE_PERIOD: f32 = 16.0;
+
 @vertex
 fn vs_main(model: VertexIn) -> VertexOut {
     var out: VertexOut;
@@ -33,50 +43,18 @@ fn vs_main(model: VertexIn) -> VertexOut {
     return out;
 }
 
-fn rand_3d(co: vec3<f32>) -> f32 {
-     return fract(sin(dot(co.xyz, vec3<f32>(12.9898,78.233,144.7272))) * 43758.5453);
-}
-
-fn perlin_layer(co: vec3<f32>) -> f32 {
-    var quant_floor = floor(co);
-    var quant_ceil = ceil(co);
-    var quant_frac = fract(co);
-
-    var bot_left_back   = rand_3d(vec3<f32>(quant_floor.x, quant_floor.y, quant_floor.z));
-    var bot_right_back  = rand_3d(vec3<f32>(quant_ceil.x, quant_floor.y, quant_floor.z));
-    var top_left_back   = rand_3d(vec3<f32>(quant_floor.x, quant_ceil.y, quant_floor.z));
-    var top_right_back  = rand_3d(vec3<f32>(quant_ceil.x, quant_ceil.y, quant_floor.z));
-
-    var bot_left_front  = rand_3d(vec3<f32>(quant_floor.x, quant_floor.y, quant_ceil.z));
-    var bot_right_front = rand_3d(vec3<f32>(quant_ceil.x, quant_floor.y, quant_ceil.z));
-    var top_left_front  = rand_3d(vec3<f32>(quant_floor.x, quant_ceil.y, quant_ceil.z));
-    var top_right_front = rand_3d(vec3<f32>(quant_ceil.x, quant_ceil.y, quant_ceil.z));
+const OCTAVES: i32 = 8;
 
-    var bot_back = mix(bot_left_back, bot_right_back, quant_frac.x);
-    var top_back = mix(top_left_back, top_right_back, quant_frac.x);
-
-    var bot_front = mix(bot_left_front, bot_right_front, quant_frac.x);
-    var top_front = mix(top_left_front, top_right_front, quant_frac.x);
-
-    var back = mix(bot_back, top_back, quant_frac.y);
-    var front = mix(bot_front, top_front, quant_frac.y);
-
-    return mix(back, front, quant_frac.z);
-}
-
-fn perlin(co: vec3<f32>) -> f32 {
-     var out_val = 0.0;
-     for (var i = 0; i < 8; i++) {
-         out_val += perlin_layer(co * pow(2.0, f32(i)));
-     }
-     return out_val / 8.0;
+// Octaves of `noise_basis` from shader_noise_lib.wgsl at doubling frequencies, averaged
+fn fbm(co: vec3<f32>) -> f32 {
+    var out_val = 0.0;
+    for (var i = 0; i < OCTAVES; i++) {
+        out_val += noise_basis(co * pow(2.0, f32(i)), 0);
+    }
+    return out_val / f32(OCTAVES);
 }
 
-@fragment
-fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
-    var uv_float = vec2<f32>(in.uv.x * u.aspect, in.uv.y) * 5.0;
-    var value = perlin(vec3<f32>(uv_float, u.time * 0.1));
-
+fn shade(value: f32) -> vec4<f32> {
     var value_s: f32;
     if value > 0.58 {
         value_s = 1.0;
@@ -89,3 +67,23 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
     var color = mix(c.bg_color, c.fg_color, value_s);
     return vec4<f32>(color, 1.0);
 }
+
+fn noise_coords(uv: vec2<f32>) -> vec3<f32> {
+    var uv_float = vec2<f32>(uv.x * u.aspect, uv.y) * 5.0;
+    return vec3<f32>(uv_float, u.time * 0.1);
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    return shade(fbm(noise_coords(in.uv)));
+}
+
+// Same background from the precomputed noise volume, which holds the first 4 octaves.
+// Sampled again at 16 times the frequency for the other 4, two filtered samples instead of 8 octaves
+@fragment
+fn fs_volume(in: VertexOut) -> @location(0) vec4<f32> {
+    var co = noise_coords(in.uv) / NOISE_PERIOD;
+    var coarse = textureSampleLevel(noise_volume, noise_sampler, co, 0.0).r;
+    var fine = textureSampleLevel(noise_volume, noise_sampler, co * 16.0, 0.0).r;
+    return shade((coarse + fine) / 2.0);
+}
@@ -0,0 +1,122 @@
+struct VertexIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct VertexOut {
+    @builtin(position) clip_position: vec4<f32>,
+    // Coordinates in the bloom mips
+    @location(0) uv: vec2<f32>,
+    // Coordinates in the input of the effect, which may be upside down
+    @location(1) source_uv: vec2<f32>,
+};
+
+// Same layout as `Effect` in shader_post.wgsl
+struct Stage {
+    kind: u32,
+    flip_y: u32,
+    texel_size: vec2<f32>,
+    // threshold, intensity, radius, levels
+    params: vec4<f32>,
+};
+
+const STAGE_PREFILTER: u32 = 0u;
+const STAGE_DOWNSAMPLE: u32 = 1u;
+const STAGE_UPSAMPLE: u32 = 2u;
+const STAGE_COMPOSITE: u32 = 3u;
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source_samp: sampler;
+
+@group(1) @binding(0)
+var<uniform> s: Stage;
+
+@group(2) @binding(0)
+var bloom: texture_2d<f32>;
+
+@group(2) @binding(1)
+var bloom_samp: sampler;
+
+@vertex
+fn vs_main(model: VertexIn) -> VertexOut {
+    var out: VertexOut;
+    out.uv = vec2<f32>(model.uv.x, 1.0 - model.uv.y);
+    out.source_uv = select(out.uv, model.uv, s.flip_y != 0u);
+    out.clip_position = vec4<f32>(model.pos, 0.0, 1.0);
+    return out;
+}
+
+// 13 tap filter from "Next Generation Post Processing in Call of Duty: Advanced Warfare",
+// `texel_size` being the size of a source texel
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    var t = s.texel_size;
+
+    var a = textureSample(source, source_samp, uv + t * vec2<f32>(-2.0, -2.0)).rgb;
+    var b = textureSample(source, source_samp, uv + t * vec2<f32>( 0.0, -2.0)).rgb;
+    var c = textureSample(source, source_samp, uv + t * vec2<f32>( 2.0, -2.0)).rgb;
+    var d = textureSample(source, source_samp, uv + t * vec2<f32>(-2.0,  0.0)).rgb;
+    var e = textureSample(source, source_samp, uv).rgb;
+    var f = textureSample(source, source_samp, uv + t * vec2<f32>( 2.0,  0.0)).rgb;
+    var g = textureSample(source, source_samp, uv + t * vec2<f32>(-2.0,  2.0)).rgb;
+    var h = textureSample(source, source_samp, uv + t * vec2<f32>( 0.0,  2.0)).rgb;
+    var i = textureSample(source, source_samp, uv + t * vec2<f32>( 2.0,  2.0)).rgb;
+    var j = textureSample(source, source_samp, uv + t * vec2<f32>(-1.0, -1.0)).rgb;
+    var k = textureSample(source, source_samp, uv + t * vec2<f32>( 1.0, -1.0)).rgb;
+    var l = textureSample(source, source_samp, uv + t * vec2<f32>(-1.0,  1.0)).rgb;
+    var m = textureSample(source, source_samp, uv + t * vec2<f32>( 1.0,  1.0)).rgb;
+
+    return e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+}
+
+// 3x3 tent filter, `radius` in source texels
+fn tent(tex: texture_2d<f32>, samp: sampler, uv: vec2<f32>, texel_size: vec2<f32>, radius: f32) -> vec3<f32> {
+    var d = vec4<f32>(1.0, 1.0, -1.0, 0.0) * texel_size.xyxy * radius;
+
+    var sum = textureSample(tex, samp, uv - d.xy).rgb;
+    sum += textureSample(tex, samp, uv - d.wy).rgb * 2.0;
+    sum += textureSample(tex, samp, uv - d.zy).rgb;
+    sum += textureSample(tex, samp, uv + d.zw).rgb * 2.0;
+    sum += textureSample(tex, samp, uv).rgb * 4.0;
+    sum += textureSample(tex, samp, uv + d.xw).rgb * 2.0;
+    sum += textureSample(tex, samp, uv + d.zy).rgb;
+    sum += textureSample(tex, samp, uv + d.wy).rgb * 2.0;
+    sum += textureSample(tex, samp, uv + d.xy).rgb;
+
+    return sum / 16.0;
+}
+
+// Keeps the part of the color above the threshold, with a soft knee of half the threshold
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    var brightness = max(color.r, max(color.g, color.b));
+    var knee = s.params.x * 0.5;
+    var soft = clamp(brightness - s.params.x + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-4);
+    var contribution = max(soft, brightness - s.params.x) / max(brightness, 1e-4);
+    return color * contribution;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    switch s.kind {
+        case STAGE_PREFILTER: {
+            return vec4<f32>(threshold(downsample(in.source_uv)), 1.0);
+        }
+        case STAGE_DOWNSAMPLE: {
+            return vec4<f32>(downsample(in.uv), 1.0);
+        }
+        case STAGE_UPSAMPLE: {
+            return vec4<f32>(tent(source, source_samp, in.uv, s.texel_size, s.params.z), 1.0);
+        }
+        default: {
+            var base = textureSample(source, source_samp, in.source_uv);
+            var glow = tent(bloom, bloom_samp, in.uv, s.texel_size, s.params.z);
+            return vec4<f32>(base.rgb + glow * s.params.y / max(s.params.w, 1.0), base.a);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Fills the noise volume sampled by `fs_volume` in shader.wgsl: the first VOLUME_OCTAVES octaves
+// of `fbm` there, with the lattice wrapped so the volume tiles in every direction. Finer octaves
+// would fall below a texel, `fs_volume` gets them by sampling the volume again at a higher frequency
+
+// Size of the volume in first octave cells, the volume repeats every PERIOD units
+override PERIOD: f32 = 16.0;
+// Edge of the volume in texels
+override SIZE: u32 = 128u;
+// Texel pairs per row of `texels`, rows are padded for the copy into the volume
+override ROW_PAIRS: u32 = 64u;
+
+// R16Float texels, two per element
+@group(0) @binding(0)
+var<storage, read_write> texels: array<u32>;
+
+const VOLUME_OCTAVES: i32 = 4;
+
+fn fbm(co: vec3<f32>) -> f32 {
+    var out_val = 0.0;
+    for (var i = 0; i < VOLUME_OCTAVES; i++) {
+        var scale = pow(2.0, f32(i));
+        out_val += noise_basis(co * scale, i32(PERIOD * scale));
+    }
+    return out_val / f32(VOLUME_OCTAVES);
+}
+
+fn noise_at(texel: vec3<u32>) -> f32 {
+    // Texel centers, where linear filtering returns the stored value
+    var co = (vec3<f32>(texel) + 0.5) / f32(SIZE) * PERIOD;
+    return fbm(co);
+}
+
+// One invocation per pair of texels along x
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= SIZE / 2u || id.y >= SIZE || id.z >= SIZE {
+        return;
+    }
+
+    var texel = vec3<u32>(id.x * 2u, id.y, id.z);
+    var pair = vec2<f32>(noise_at(texel), noise_at(texel + vec3<u32>(1u, 0u, 0u)));
+    texels[(id.z * SIZE + id.y) * ROW_PAIRS + id.x] = pack2x16float(pair);
+}
@@ -0,0 +1,159 @@
+// Noise functions the backgrounds are built from, appended to shader.wgsl and shader_noise.wgsl.
+// Mirrored step by step on the CPU in src/noise_lib.rs, keep the two in sync
+
+// Which function `noise_basis` uses, `NoiseBasis` in src/noise_lib.rs
+override NOISE_BASIS: u32 = 1u;
+
+const BASIS_VALUE: u32 = 0u;
+const BASIS_PERLIN: u32 = 1u;
+const BASIS_SIMPLEX: u32 = 2u;
+const BASIS_WORLEY: u32 = 3u;
+
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski and Olano.
+// Integer only, so every GPU gets the same bits unlike the usual fract(sin(...))
+fn hash_u32(x: u32) -> u32 {
+    var state = x * 747796405u + 2891336453u;
+    var word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn hash_cell(cell: vec3<i32>) -> u32 {
+    var c = bitcast<vec3<u32>>(cell);
+    return hash_u32(c.x ^ hash_u32(c.y ^ hash_u32(c.z)));
+}
+
+// Uniform in [0, 1)
+fn hash_to_unit(h: u32) -> f32 {
+    return f32(h >> 8u) / 16777216.0;
+}
+
+// Lattice cells repeat every `period` cells along each axis, 0 doesn't repeat
+fn wrap_cell(cell: vec3<i32>, period: i32) -> vec3<i32> {
+    if period <= 0 {
+        return cell;
+    }
+    return ((cell % period) + period) % period;
+}
+
+// Quintic fade, with zero first and second derivatives at the lattice points
+fn fade(t: vec3<f32>) -> vec3<f32> {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn value_corner(cell: vec3<i32>, offset: vec3<i32>, period: i32) -> f32 {
+    return hash_to_unit(hash_cell(wrap_cell(cell + offset, period)));
+}
+
+// In [0, 1]
+fn value_noise(p: vec3<f32>, period: i32) -> f32 {
+    var cell = vec3<i32>(floor(p));
+    var t = fade(p - floor(p));
+
+    var x00 = mix(value_corner(cell, vec3<i32>(0, 0, 0), period), value_corner(cell, vec3<i32>(1, 0, 0), period), t.x);
+    var x10 = mix(value_corner(cell, vec3<i32>(0, 1, 0), period), value_corner(cell, vec3<i32>(1, 1, 0), period), t.x);
+    var x01 = mix(value_corner(cell, vec3<i32>(0, 0, 1), period), value_corner(cell, vec3<i32>(1, 0, 1), period), t.x);
+    var x11 = mix(value_corner(cell, vec3<i32>(0, 1, 1), period), value_corner(cell, vec3<i32>(1, 1, 1), period), t.x);
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+// Dot product of `f` with one of the 12 gradients pointing to the edges of a cube, picked by `h`
+fn gradient(h: u32, f: vec3<f32>) -> f32 {
+    var h4 = h & 15u;
+    var u = select(f.y, f.x, h4 < 8u);
+    var v = select(select(f.z, f.x, h4 == 12u || h4 == 14u), f.y, h4 < 4u);
+    return select(-u, u, (h4 & 1u) == 0u) + select(-v, v, (h4 & 2u) == 0u);
+}
+
+fn perlin_corner(cell: vec3<i32>, offset: vec3<i32>, f: vec3<f32>, period: i32) -> f32 {
+    return gradient(hash_cell(wrap_cell(cell + offset, period)), f - vec3<f32>(offset));
+}
+
+// In [-1, 1]
+fn perlin_noise(p: vec3<f32>, period: i32) -> f32 {
+    var cell = vec3<i32>(floor(p));
+    var f = p - floor(p);
+    var t = fade(f);
+
+    var x00 = mix(perlin_corner(cell, vec3<i32>(0, 0, 0), f, period), perlin_corner(cell, vec3<i32>(1, 0, 0), f, period), t.x);
+    var x10 = mix(perlin_corner(cell, vec3<i32>(0, 1, 0), f, period), perlin_corner(cell, vec3<i32>(1, 1, 0), f, period), t.x);
+    var x01 = mix(perlin_corner(cell, vec3<i32>(0, 0, 1), f, period), perlin_corner(cell, vec3<i32>(1, 0, 1), f, period), t.x);
+    var x11 = mix(perlin_corner(cell, vec3<i32>(0, 1, 1), f, period), perlin_corner(cell, vec3<i32>(1, 1, 1), f, period), t.x);
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+// Contribution of a simplex corner at `x` from the sample point
+fn simplex_corner(cell: vec3<i32>, x: vec3<f32>) -> f32 {
+    var t = 0.6 - dot(x, x);
+    if t <= 0.0 {
+        return 0.0;
+    }
+    var t2 = t * t;
+    return t2 * t2 * gradient(hash_cell(cell), x);
+}
+
+// In [-1, 1]
+fn simplex_noise(p: vec3<f32>) -> f32 {
+    const F3 = 1.0 / 3.0;
+    const G3 = 1.0 / 6.0;
+
+    // Skew into the lattice of cubes split into 6 simplices, and back
+    var s = (p.x + p.y + p.z) * F3;
+    var i = floor(p + s);
+    var t = (i.x + i.y + i.z) * G3;
+    var x0 = p - (i - t);
+
+    // Which simplex of the cube, by the order of the coordinates
+    var g = step(x0.yzx, x0.xyz);
+    var l = 1.0 - g;
+    var i1 = min(g, l.zxy);
+    var i2 = max(g, l.zxy);
+
+    var x1 = x0 - i1 + G3;
+    var x2 = x0 - i2 + 2.0 * G3;
+    var x3 = x0 - 1.0 + 3.0 * G3;
+
+    var cell = vec3<i32>(i);
+    var n = simplex_corner(cell, x0)
+        + simplex_corner(cell + vec3<i32>(i1), x1)
+        + simplex_corner(cell + vec3<i32>(i2), x2)
+        + simplex_corner(cell + vec3<i32>(1, 1, 1), x3);
+    return 32.0 * n;
+}
+
+// Distance to the nearest feature point, one per cell. Mostly in [0, 1]
+fn worley_noise(p: vec3<f32>, period: i32) -> f32 {
+    var cell = vec3<i32>(floor(p));
+    var f = p - floor(p);
+
+    var min_dist = 8.0;
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                var offset = vec3<i32>(x, y, z);
+                var h = hash_cell(wrap_cell(cell + offset, period));
+                var point = vec3<f32>(hash_to_unit(h), hash_to_unit(hash_u32(h)), hash_to_unit(hash_u32(hash_u32(h))));
+                var d = vec3<f32>(offset) + point - f;
+                min_dist = min(min_dist, dot(d, d));
+            }
+        }
+    }
+    return sqrt(min_dist);
+}
+
+// NOISE_BASIS mapped to [0, 1]. Simplex noise ignores `period`
+fn noise_basis(p: vec3<f32>, period: i32) -> f32 {
+    switch NOISE_BASIS {
+        case BASIS_VALUE: {
+            return value_noise(p, period);
+        }
+        case BASIS_SIMPLEX: {
+            return simplex_noise(p) * 0.5 + 0.5;
+        }
+        case BASIS_WORLEY: {
+            return 1.0 - worley_noise(p, period);
+        }
+        default: {
+            return perlin_noise(p, period) * 0.5 + 0.5;
+        }
+    }
+}
@@ -13,6 +13,24 @@ struct Uniforms {
     aspect: f32,
 };
 
+struct Effect {
+    kind: u32,
+    flip_y: u32,
+    texel_size: vec2<f32>,
+    params: vec4<f32>,
+};
+
+const EFFECT_COPY: u32 = 0u;
+const EFFECT_WARP: u32 = 1u;
+// 2 is bloom, drawn with shader_bloom.wgsl
+const EFFECT_BLUR: u32 = 3u;
+const EFFECT_VIGNETTE: u32 = 4u;
+const EFFECT_CHROMATIC_ABERRATION: u32 = 5u;
+const EFFECT_SCANLINES: u32 = 6u;
+const EFFECT_COLOR_GRADE: u32 = 7u;
+
+const PI: f32 = 3.141592653589793;
+
 @group(0) @binding(0)
 var<uniform> u: Uniforms;
 
@@ -22,19 +40,106 @@ var texture: texture_2d<f32>;
 @group(1) @binding(1)
 var samp: sampler;
 
+@group(2) @binding(0)
+var<uniform> e: Effect;
+
 @vertex
 fn vs_main(model: VertexIn) -> VertexOut {
     var out: VertexOut;
+    // The quad's uv maps the top row of the texture to the bottom of the output,
+    // which only the first effect wants, to undo the upside down scene
     out.uv = model.uv;
+    if e.flip_y == 0u {
+        out.uv.y = 1.0 - out.uv.y;
+    }
     out.clip_position = vec4<f32>(model.pos, 0.0, 1.0);
     return out;
 }
 
-@fragment
-fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
-    var pi = 3.141592653589793;
+// params: strength, speed
+fn warp(uv: vec2<f32>) -> vec4<f32> {
+    var t = u.time * e.params.y;
+    var offset = vec2<f32>(cos(t * 0.45 + 2.0 * PI * uv.y) * 0.05, sin(t * 0.6 + 2.0 * PI * uv.x) * 0.06);
+    return textureSample(texture, samp, uv + offset * e.params.x);
+}
+
+// 5x5 gaussian, `radius` in pixels
+fn gaussian(uv: vec2<f32>, radius: f32) -> vec4<f32> {
+    var weights = array<f32, 5>(1.0, 4.0, 6.0, 4.0, 1.0);
+    var step = e.texel_size * radius / 2.0;
+    var sum = vec4<f32>(0.0);
+
+    for (var y = 0; y < 5; y++) {
+        for (var x = 0; x < 5; x++) {
+            var offset = vec2<f32>(f32(x - 2), f32(y - 2)) * step;
+            sum += textureSample(texture, samp, uv + offset) * weights[x] * weights[y];
+        }
+    }
+
+    return sum / 256.0;
+}
+
+// params: radius
+fn blur(uv: vec2<f32>) -> vec4<f32> {
+    return gaussian(uv, e.params.x);
+}
 
-    var uv_float = vec2<f32>(in.uv.x + cos(u.time * 0.45 + 2.0 * pi * in.uv.y) * 0.05, in.uv.y + sin(u.time * 0.6 + 2.0 * pi * in.uv.x) * 0.06);
+// params: intensity, radius, smoothness
+fn vignette(uv: vec2<f32>) -> vec4<f32> {
+    var color = textureSample(texture, samp, uv);
+    var centered = (uv - 0.5) * vec2<f32>(u.aspect, 1.0);
+    var falloff = smoothstep(e.params.y, e.params.y + e.params.z, length(centered));
+    return vec4<f32>(color.rgb * (1.0 - falloff * e.params.x), color.a);
+}
+
+// params: offset in pixels at the screen edge
+fn chromatic_aberration(uv: vec2<f32>) -> vec4<f32> {
+    var dir = (uv - 0.5) * 2.0 * e.texel_size * e.params.x;
+    var r = textureSample(texture, samp, uv + dir).r;
+    var ga = textureSample(texture, samp, uv).ga;
+    var b = textureSample(texture, samp, uv - dir).b;
+    return vec4<f32>(r, ga.x, b, ga.y);
+}
+
+// params: intensity, line count (0 uses one line per 3 pixels), curvature
+fn scanlines(uv: vec2<f32>) -> vec4<f32> {
+    var centered = uv * 2.0 - 1.0;
+    centered *= 1.0 + e.params.z * dot(centered.yx, centered.yx);
+    var curved = centered * 0.5 + 0.5;
+
+    var color = textureSample(texture, samp, curved);
+    var inside = all(curved >= vec2<f32>(0.0)) && all(curved <= vec2<f32>(1.0));
+
+    var lines = select(e.params.y, 1.0 / (3.0 * e.texel_size.y), e.params.y <= 0.0);
+    var line = sin(curved.y * lines * PI);
+    var shade = 1.0 - e.params.x * (1.0 - line * line);
+
+    return select(vec4<f32>(0.0, 0.0, 0.0, 1.0), vec4<f32>(color.rgb * shade, color.a), inside);
+}
+
+// params: exposure in stops, contrast, saturation, gamma
+fn color_grade(uv: vec2<f32>) -> vec4<f32> {
+    var color = textureSample(texture, samp, uv);
+    var rgb = color.rgb * exp2(e.params.x);
+    rgb = (rgb - 0.5) * e.params.y + 0.5;
 
-    return textureSample(texture, samp, uv_float);
+    var luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, e.params.z);
+    // Gamma of 0 would divide by zero, a negative one would invert the curve
+    rgb = pow(max(rgb, vec3<f32>(0.0)), vec3<f32>(1.0 / max(e.params.w, 1e-3)));
+
+    return vec4<f32>(rgb, color.a);
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    switch e.kind {
+        case EFFECT_WARP: { return warp(in.uv); }
+        case EFFECT_BLUR: { return blur(in.uv); }
+        case EFFECT_VIGNETTE: { return vignette(in.uv); }
+        case EFFECT_CHROMATIC_ABERRATION: { return chromatic_aberration(in.uv); }
+        case EFFECT_SCANLINES: { return scanlines(in.uv); }
+        case EFFECT_COLOR_GRADE: { return color_grade(in.uv); }
+        default: { return textureSample(texture, samp, in.uv); }
+    }
 }
@@ -3,26 +3,43 @@ struct VertexIn {
     @location(1) uv: vec2<f32>,
 };
 
+struct GlyphIn {
+    @location(2) rect: vec4<f32>,
+    @location(3) uv_rect: vec4<f32>,
+};
+
 struct VertexOut {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
 };
 
+struct TextUniforms {
+    screen_size: vec2<f32>,
+    // -1 flips the rows for targets that aren't sampled by the post effects
+    y_axis: f32,
+};
+
 @group(0) @binding(0)
 var texture: texture_2d<f32>;
 
 @group(0) @binding(1)
 var samp: sampler;
 
+@group(1) @binding(0)
+var<uniform> t: TextUniforms;
+
 @vertex
-fn vs_main(model: VertexIn) -> VertexOut {
+fn vs_main(model: VertexIn, glyph: GlyphIn) -> VertexOut {
     var out: VertexOut;
-    out.uv = model.uv;
-    out.clip_position = vec4<f32>(model.pos, 0.0, 1.0);
+    var px = glyph.rect.xy + model.uv * glyph.rect.zw;
+    out.uv = mix(glyph.uv_rect.xy, glyph.uv_rect.zw, model.uv);
+    var clip = px / t.screen_size * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(clip.x, clip.y * t.y_axis, 0.0, 1.0);
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
-    return textureSample(texture, samp, in.uv);
+    var coverage = textureSample(texture, samp, in.uv).r;
+    return vec4<f32>(coverage);
 }
@@ -0,0 +1,90 @@
+struct VertexIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct GlyphIn {
+    @location(2) rect: vec4<f32>,
+    @location(3) uv_rect: vec4<f32>,
+};
+
+struct VertexOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) uv_rect: vec4<f32>,
+    @location(2) uv_per_px: vec2<f32>,
+};
+
+struct TextUniforms {
+    screen_size: vec2<f32>,
+    // -1 flips the rows for targets that aren't sampled by the post effects
+    y_axis: f32,
+};
+
+struct SdfParams {
+    fill_color: vec4<f32>,
+    outline_color: vec4<f32>,
+    shadow_color: vec4<f32>,
+    glow_color: vec4<f32>,
+    shadow_offset: vec2<f32>,
+    outline_width: f32,
+    glow_width: f32,
+    shadow_softness: f32,
+    distance_scale: f32,
+};
+
+@group(0) @binding(0)
+var texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var samp: sampler;
+
+@group(1) @binding(0)
+var<uniform> t: TextUniforms;
+
+@group(2) @binding(0)
+var<uniform> p: SdfParams;
+
+@vertex
+fn vs_main(model: VertexIn, glyph: GlyphIn) -> VertexOut {
+    var out: VertexOut;
+    var px = glyph.rect.xy + model.uv * glyph.rect.zw;
+    out.uv = mix(glyph.uv_rect.xy, glyph.uv_rect.zw, model.uv);
+    out.uv_rect = glyph.uv_rect;
+    out.uv_per_px = (glyph.uv_rect.zw - glyph.uv_rect.xy) / glyph.rect.zw;
+    var clip = px / t.screen_size * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(clip.x, clip.y * t.y_axis, 0.0, 1.0);
+    return out;
+}
+
+// Signed distance to the glyph edge in screen pixels, positive inside
+fn distance_at(uv: vec2<f32>, uv_rect: vec4<f32>) -> f32 {
+    var clamped = clamp(uv, uv_rect.xy, uv_rect.zw);
+    return (textureSample(texture, samp, clamped).r - 0.5) * p.distance_scale;
+}
+
+fn premultiply(color: vec4<f32>, coverage: f32) -> vec4<f32> {
+    var a = color.a * coverage;
+    return vec4<f32>(color.rgb * a, a);
+}
+
+fn over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    return top + bottom * (1.0 - top.a);
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var d = distance_at(in.uv, in.uv_rect);
+    var d_shadow = distance_at(in.uv - p.shadow_offset * in.uv_per_px, in.uv_rect);
+
+    var fill = clamp(d + 0.5, 0.0, 1.0);
+    var outline = clamp(d + p.outline_width + 0.5, 0.0, 1.0) * step(0.001, p.outline_width);
+    var glow = (1.0 - smoothstep(0.0, max(p.glow_width, 0.001), -d)) * step(0.001, p.glow_width);
+    var shadow = smoothstep(-p.shadow_softness - 0.5, p.shadow_softness + 0.5, d_shadow);
+
+    var color = premultiply(p.shadow_color, shadow);
+    color = over(premultiply(p.glow_color, glow), color);
+    color = over(premultiply(p.outline_color, outline), color);
+    color = over(premultiply(p.fill_color, fill), color);
+    return color;
+}
@@ -0,0 +1,117 @@
+struct VertexIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct VertexOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Tonemap {
+    op: u32,
+    // Linear multiplier, 2^stops
+    exposure: f32,
+    // Non-zero when the output format doesn't encode to sRGB by itself
+    encode_srgb: u32,
+};
+
+const TONEMAP_NONE: u32 = 0u;
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+const TONEMAP_AGX: u32 = 3u;
+
+@group(0) @binding(0)
+var texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var samp: sampler;
+
+@group(1) @binding(0)
+var<uniform> t: Tonemap;
+
+@vertex
+fn vs_main(model: VertexIn) -> VertexOut {
+    var out: VertexOut;
+    out.uv = vec2<f32>(model.uv.x, 1.0 - model.uv.y);
+    out.clip_position = vec4<f32>(model.pos, 0.0, 1.0);
+    return out;
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms.
+// The matrices are written row by row, so colors are multiplied from the left
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    var input = mat3x3<f32>(
+        0.59719, 0.35458, 0.04823,
+        0.07600, 0.90834, 0.01566,
+        0.02840, 0.13383, 0.83777,
+    );
+    var output = mat3x3<f32>(
+         1.60475, -0.53108, -0.07367,
+        -0.10208,  1.10813, -0.00605,
+        -0.00327, -0.07276,  1.07602,
+    );
+
+    var v = color * input;
+    v = (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081);
+    return clamp(v * output, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial approximation of the AgX base contrast curve
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    var x2 = x * x;
+    var x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    var inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    var outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    var min_ev = -12.47393;
+    var max_ev = 4.026069;
+
+    var v = inset * color;
+    v = clamp(log2(max(v, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+    v = outset * agx_contrast(v);
+
+    // The curve produces display encoded values, back to linear for the sRGB encoding below
+    return pow(clamp(v, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    var low = color * 12.92;
+    var high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var hdr = textureSample(texture, samp, in.uv);
+    var color = max(hdr.rgb * t.exposure, vec3<f32>(0.0));
+
+    switch t.op {
+        case TONEMAP_REINHARD: { color = reinhard(color); }
+        case TONEMAP_ACES: { color = aces(color); }
+        case TONEMAP_AGX: { color = agx(color); }
+        default: { color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)); }
+    }
+
+    if t.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+
+    return vec4<f32>(color, hdr.a);
+}
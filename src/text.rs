@@ -0,0 +1,726 @@
+use ab_glyph::{point, Font, GlyphId, PxScale};
+use wgpu::util::DeviceExt;
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    font::FontChain,
+    hot_reload::Shader,
+    layout::{layout, Align, LayoutOptions, PositionedGlyph},
+    renderer::{text_blend_state, texture_bind_group_layout, Multisample, PipelineBuilder, PipelineError, Vertex, QUAD},
+    sdf::{generate_sdf, sdf_params_bind_group_layout, SdfOptions, SdfParams, SDF_SIZE, SDF_SPREAD},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextMode {
+    /// Coverage bitmaps rasterized at the font size, inverting the background
+    #[default]
+    Bitmap,
+    /// Distance fields rasterized once at a fixed size, scaled freely and
+    /// drawn with optional outline, drop shadow and glow
+    Sdf,
+}
+
+impl TextMode {
+    pub fn shader(self) -> Shader {
+        match self {
+            TextMode::Bitmap => Shader::Text,
+            TextMode::Sdf => Shader::TextSdf,
+        }
+    }
+}
+
+/// Where the text block is placed on the target
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TextAnchor {
+    #[default]
+    Center,
+    /// Top left corner of the block at this many pixels from the top left of the target
+    TopLeft([f32; 2]),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct TextOptions {
+    /// Text shown in the middle of the screen
+    #[arg(long, default_value = "Абоба", value_parser = parse_text)]
+    pub text: String,
+
+    /// Font file or installed font family. Can be repeated to build a fallback
+    /// chain, glyphs missing in one font are taken from the next
+    #[arg(long, value_name = "PATH|FAMILY")]
+    pub font: Vec<String>,
+
+    /// Font size in pixels
+    #[arg(long, default_value_t = 256.0, value_parser = parse_font_size)]
+    pub font_size: f32,
+
+    /// Horizontal alignment of the lines
+    #[arg(long, value_enum, default_value_t = Align::Center)]
+    pub align: Align,
+
+    /// Wrap lines at word boundaries to fit this width in pixels
+    #[arg(long, value_name = "PIXELS", value_parser = parse_wrap_width)]
+    pub wrap_width: Option<f32>,
+
+    /// Line height multiplier
+    #[arg(long, default_value_t = 1.0, value_parser = parse_line_spacing)]
+    pub line_spacing: f32,
+
+    /// How glyphs are rasterized and drawn
+    #[arg(long, value_enum, default_value_t = TextMode::Bitmap)]
+    pub text_mode: TextMode,
+
+    #[command(flatten)]
+    pub sdf: SdfOptions,
+}
+
+impl TextOptions {
+    pub fn layout_options(&self) -> LayoutOptions {
+        LayoutOptions {
+            max_width: self.wrap_width,
+            align: self.align,
+            line_spacing: self.line_spacing,
+        }
+    }
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        TextOptions {
+            text: "Абоба".into(),
+            font: vec![],
+            font_size: 256.0,
+            align: Align::Center,
+            wrap_width: None,
+            line_spacing: 1.0,
+            text_mode: TextMode::Bitmap,
+            sdf: SdfOptions::default(),
+        }
+    }
+}
+
+fn parse_text(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        return Err("text must not be empty".into());
+    }
+    Ok(s.into())
+}
+
+fn parse_wrap_width(s: &str) -> Result<f32, String> {
+    let width: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(width > 0.0 && width.is_finite()) {
+        return Err("wrap width must be a positive number of pixels".into());
+    }
+    Ok(width)
+}
+
+fn parse_line_spacing(s: &str) -> Result<f32, String> {
+    let spacing: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(0.1..=10.0).contains(&spacing) {
+        return Err("line spacing must be between 0.1 and 10".into());
+    }
+    Ok(spacing)
+}
+
+fn parse_font_size(s: &str) -> Result<f32, String> {
+    let size: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(1.0..=2048.0).contains(&size) {
+        return Err("font size must be between 1 and 2048 pixels".into());
+    }
+    Ok(size)
+}
+
+/// One glyph quad, drawn as an instance of the unit quad
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlyphInstance {
+    /// Top left corner and size in pixels, y pointing down
+    pub rect: [f32; 4],
+    /// Top left and bottom right corners in the atlas
+    pub uv_rect: [f32; 4],
+}
+
+impl GlyphInstance {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GlyphInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextUniforms {
+    pub screen_size: [f32; 2],
+    /// 1 keeps pixel rows the way the post effects sample the scene, -1 flips them for targets
+    /// shown as they are drawn
+    pub y_axis: f32,
+    _padding: f32,
+}
+
+impl TextUniforms {
+    pub fn new(screen_size: [f32; 2], flip_y: bool) -> Self {
+        TextUniforms { screen_size, y_axis: if flip_y { -1.0 } else { 1.0 }, _padding: 0.0 }
+    }
+}
+
+/// Layout of `TextUniforms` at binding 0
+pub fn text_uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("text_uniform_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_index: usize,
+    glyph: GlyphId,
+    scale_bits: u32,
+}
+
+/// Location of a rasterized glyph in the atlas
+#[derive(Clone, Copy, Debug)]
+struct AtlasGlyph {
+    /// Offset of the bitmap from the pen position on the baseline, in pixels
+    offset: [f32; 2],
+    size: [f32; 2],
+    uv_rect: [f32; 4],
+}
+
+/// Result of looking a glyph up in the atlas
+enum AtlasEntry {
+    Cached(AtlasGlyph),
+    /// The glyph draws nothing, like a space, or is too big for the atlas
+    Empty,
+    /// No room left in the atlas for the glyph
+    Full,
+}
+
+impl From<Option<AtlasGlyph>> for AtlasEntry {
+    fn from(glyph: Option<AtlasGlyph>) -> Self {
+        glyph.map_or(AtlasEntry::Empty, AtlasEntry::Cached)
+    }
+}
+
+/// Padding around every glyph, so linear filtering doesn't pick up neighbours
+const ATLAS_PADDING: u32 = 1;
+
+/// Glyphs are rasterized at most at a quarter of the atlas size, so even the widest fit a few per row.
+/// Bigger text is drawn from these glyphs scaled up
+const ATLAS_GLYPHS_PER_ROW: u32 = 4;
+
+/// `R8Unorm` texture holding glyph coverage or distance fields, packed into shelves
+/// (rows of glyphs, each as high as its tallest glyph)
+struct GlyphAtlas {
+    texture: wgpu::Texture,
+    size: u32,
+    mode: TextMode,
+    cursor: (u32, u32),
+    shelf_height: u32,
+    /// `None` for glyphs that don't produce any pixels, like spaces
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device, size: u32, mode: TextMode) -> Self {
+        let texture = device.create_texture(&wgpu::wgt::TextureDescriptor {
+            label: Some("glyph_atlas"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        GlyphAtlas {
+            texture, size, mode,
+            cursor: (ATLAS_PADDING, ATLAS_PADDING),
+            shelf_height: 0,
+            glyphs: HashMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cursor = (ATLAS_PADDING, ATLAS_PADDING);
+        self.shelf_height = 0;
+        self.glyphs.clear();
+    }
+
+    /// Finds space for a `w`x`h` bitmap, or `None` if the atlas is full
+    fn allocate(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        if self.cursor.0 + w + ATLAS_PADDING > self.size {
+            self.cursor = (ATLAS_PADDING, self.cursor.1 + self.shelf_height + ATLAS_PADDING);
+            self.shelf_height = 0;
+        }
+
+        if self.cursor.0 + w + ATLAS_PADDING > self.size || self.cursor.1 + h + ATLAS_PADDING > self.size {
+            return None;
+        }
+
+        let pos = self.cursor;
+        self.cursor.0 += w + ATLAS_PADDING;
+        self.shelf_height = self.shelf_height.max(h);
+        Some(pos)
+    }
+
+    /// Returns the cached glyph, rasterizing and uploading it on first use
+    fn get(&mut self, queue: &wgpu::Queue, fonts: &FontChain, key: GlyphKey) -> AtlasEntry {
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return (*glyph).into();
+        }
+
+        let scale = PxScale::from(f32::from_bits(key.scale_bits));
+        let font = fonts.font(key.font_index);
+
+        let Some(outline) = font.outline_glyph(key.glyph.with_scale_and_position(scale, point(0.0, 0.0))) else {
+            self.glyphs.insert(key, None);
+            return AtlasEntry::Empty;
+        };
+
+        let bounds = outline.px_bounds();
+        if bounds.width() < 1.0 || bounds.height() < 1.0 {
+            self.glyphs.insert(key, None);
+            return AtlasEntry::Empty;
+        }
+
+        // Distance fields need room around the glyph for the outside distances
+        let pad = if self.mode == TextMode::Sdf { SDF_SPREAD } else { 0 };
+        let (w, h) = (bounds.width() as u32 + 2 * pad, bounds.height() as u32 + 2 * pad);
+
+        // Would never fit, even in an empty atlas
+        if w + 2 * ATLAS_PADDING > self.size || h + 2 * ATLAS_PADDING > self.size {
+            log::warn!("Glyph of {w}x{h} pixels doesn't fit the glyph atlas, it is skipped");
+            self.glyphs.insert(key, None);
+            return AtlasEntry::Empty;
+        }
+
+        let Some((x, y)) = self.allocate(w, h) else {
+            return AtlasEntry::Full;
+        };
+
+        let mut pixels = vec![0u8; (w * h) as usize];
+        outline.draw(|gx, gy, coverage| {
+            let (gx, gy) = (gx + pad, gy + pad);
+            if gx < w && gy < h {
+                pixels[(gy * w + gx) as usize] = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+            }
+        });
+
+        if self.mode == TextMode::Sdf {
+            pixels = generate_sdf(&pixels, w, h, SDF_SPREAD);
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(w),
+                rows_per_image: Some(h),
+            },
+            wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let atlas_size = self.size as f32;
+        let glyph = AtlasGlyph {
+            offset: [bounds.min.x - pad as f32, bounds.min.y - pad as f32],
+            size: [w as f32, h as f32],
+            uv_rect: [
+                x as f32 / atlas_size,
+                y as f32 / atlas_size,
+                (x + w) as f32 / atlas_size,
+                (y + h) as f32 / atlas_size,
+            ],
+        };
+
+        self.glyphs.insert(key, Some(glyph));
+        AtlasEntry::Cached(glyph)
+    }
+}
+
+/// Draws the text block centered on the render target, or wherever its `TextAnchor` puts it,
+/// one instanced quad per glyph sampled from a glyph atlas. Changing the text only
+/// re-rasterizes glyphs that weren't drawn before.
+///
+/// In `TextMode::Sdf` glyphs are rasterized at `SDF_SIZE` and blended over the scene
+/// with premultiplied alpha instead of inverting it
+pub struct TextRenderer {
+    fonts: Arc<FontChain>,
+    scale: PxScale,
+    mode: TextMode,
+    layout_options: LayoutOptions,
+    anchor: TextAnchor,
+    flip_y: bool,
+    text: String,
+    dirty: bool,
+
+    atlas: GlyphAtlas,
+    pipeline: wgpu::RenderPipeline,
+    target_format: wgpu::TextureFormat,
+    multisample: Multisample,
+    cache: Option<wgpu::PipelineCache>,
+
+    atlas_bind_group_layout: wgpu::BindGroupLayout,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    sdf_bind_group_layout: wgpu::BindGroupLayout,
+
+    quad_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    instance_count: u32,
+    uniform_buffer: wgpu::Buffer,
+    sdf_buffer: wgpu::Buffer,
+
+    atlas_bind_group: wgpu::BindGroup,
+    uniform_bind_group: wgpu::BindGroup,
+    sdf_bind_group: wgpu::BindGroup,
+
+    target_size: (u32, u32),
+}
+
+/// `layouts` are the atlas, text uniform and SDF params layouts, the last one is only used by `TextMode::Sdf`
+fn text_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    multisample: Multisample,
+    cache: Option<&wgpu::PipelineCache>,
+    mode: TextMode,
+    layouts: [&wgpu::BindGroupLayout; 3],
+    source: &str,
+) -> Result<wgpu::RenderPipeline, PipelineError> {
+    let builder = PipelineBuilder::new(device, format, source)
+        .with_label(mode.shader().file_name())
+        .with_multisample(multisample)
+        .with_cache(cache)
+        .with_buffer(Vertex::desc())
+        .with_buffer(GlyphInstance::desc())
+        .with_bind_group(layouts[0])
+        .with_bind_group(layouts[1]);
+
+    match mode {
+        TextMode::Bitmap => builder
+            .with_blending(text_blend_state())
+            .build(),
+        TextMode::Sdf => builder
+            .with_bind_group(layouts[2])
+            .with_blending(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+            .build(),
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Glyph instance buffer"),
+        size: (capacity * std::mem::size_of::<GlyphInstance>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl TextRenderer {
+    /// `multisample` is the sample count of the target and the features of `target_format`.
+    /// `fonts` is usually loaded from `options.font` and shared with other text renderers
+    pub fn new(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        multisample: Multisample,
+        cache: Option<&wgpu::PipelineCache>,
+        fonts: Arc<FontChain>,
+        options: &TextOptions,
+    ) -> anyhow::Result<Self> {
+        let atlas_size = device.limits().max_texture_dimension_2d.min(2048);
+        let atlas = GlyphAtlas::new(device, atlas_size, options.text_mode);
+
+        let quad_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Glyph quad buffer"),
+            contents: bytemuck::cast_slice(QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text uniform buffer"),
+            size: std::mem::size_of::<TextUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let atlas_view = atlas.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let atlas_sampler = device.create_sampler(&wgpu::wgt::SamplerDescriptor {
+            label: Some("glyph_atlas_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let atlas_bind_group_layout = texture_bind_group_layout(device);
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("glyph_atlas_bind_group"),
+            layout: &atlas_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas_sampler),
+                },
+            ],
+        });
+
+        let uniform_bind_group_layout = text_uniform_bind_group_layout(device);
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("text_uniform_bind_group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let sdf_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SDF params buffer"),
+            contents: bytemuck::cast_slice(&[options.sdf.params().clamped(options.font_size)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let sdf_bind_group_layout = sdf_params_bind_group_layout(device);
+        let sdf_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sdf_params_bind_group"),
+            layout: &sdf_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sdf_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline = text_pipeline(
+            device, target_format, multisample, cache, options.text_mode,
+            [&atlas_bind_group_layout, &uniform_bind_group_layout, &sdf_bind_group_layout],
+            options.text_mode.shader().builtin(),
+        )?;
+
+        Ok(TextRenderer {
+            fonts,
+            scale: PxScale::from(options.font_size),
+            mode: options.text_mode,
+            layout_options: options.layout_options(),
+            anchor: TextAnchor::Center,
+            flip_y: false,
+            text: options.text.clone(),
+            dirty: true,
+            atlas, pipeline, target_format, multisample,
+            cache: cache.cloned(),
+            atlas_bind_group_layout, uniform_bind_group_layout, sdf_bind_group_layout,
+            quad_buffer,
+            instance_buffer: create_instance_buffer(device, 64),
+            instance_count: 0,
+            uniform_buffer, sdf_buffer,
+            atlas_bind_group, uniform_bind_group, sdf_bind_group,
+            target_size: (0, 0),
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn fonts(&self) -> &Arc<FontChain> {
+        &self.fonts
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text = text.into();
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_anchor(&mut self, anchor: TextAnchor) {
+        if self.anchor != anchor {
+            self.anchor = anchor;
+            self.dirty = true;
+        }
+    }
+
+    /// Draws for a target shown as it is, like the window surface, instead of one sampled
+    /// by the post effects
+    pub fn set_flip_y(&mut self, flip_y: bool) {
+        if self.flip_y != flip_y {
+            self.flip_y = flip_y;
+            self.dirty = true;
+        }
+    }
+
+    pub fn mode(&self) -> TextMode {
+        self.mode
+    }
+
+    /// Sets the outline, shadow and glow of `TextMode::Sdf`, clamped to what
+    /// the distance field can represent at the current font size
+    pub fn set_sdf_params(&self, queue: &wgpu::Queue, params: SdfParams) {
+        queue.write_buffer(&self.sdf_buffer, 0, bytemuck::cast_slice(&[params.clamped(self.scale.y)]));
+    }
+
+    /// Rebuilds the pipeline from `source` if `shader` is the one used by the current mode.
+    /// On error the previous pipeline is kept
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        if shader != self.mode.shader() {
+            return Ok(());
+        }
+
+        let layouts = [&self.atlas_bind_group_layout, &self.uniform_bind_group_layout, &self.sdf_bind_group_layout];
+        self.pipeline = text_pipeline(device, self.target_format, self.multisample, self.cache.as_ref(), self.mode, layouts, source)?;
+        Ok(())
+    }
+
+    /// Scale glyphs are rasterized at
+    fn raster_scale(&self) -> PxScale {
+        let max = (self.atlas.size / ATLAS_GLYPHS_PER_ROW) as f32;
+        match self.mode {
+            TextMode::Bitmap if self.scale.y > max => PxScale { x: self.scale.x * max / self.scale.y, y: max },
+            TextMode::Bitmap => self.scale,
+            TextMode::Sdf => PxScale::from(SDF_SIZE),
+        }
+    }
+
+    /// Builds the glyph instances for the current text, rasterizing glyphs missing from the atlas.
+    /// Cheap when neither the text nor the target size changed
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, target_size: (u32, u32)) {
+        if !self.dirty && self.target_size == target_size {
+            return;
+        }
+
+        self.dirty = false;
+        self.target_size = target_size;
+
+        let screen_size = [target_size.0 as f32, target_size.1 as f32];
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[TextUniforms::new(screen_size, self.flip_y)]));
+
+        let layout = layout(&self.fonts, &self.text, self.scale, &self.layout_options);
+        let glyphs = layout.glyphs;
+        let origin = match self.anchor {
+            TextAnchor::Center => [
+                ((target_size.0 as f32 - layout.size[0]) / 2.0).round(),
+                ((target_size.1 as f32 - layout.size[1]) / 2.0).round(),
+            ],
+            TextAnchor::TopLeft(offset) => offset.map(f32::round),
+        };
+
+        let instances = match self.build_instances(queue, &glyphs, origin) {
+            Some(instances) => instances,
+            None => {
+                // Atlas is full of glyphs from older text, start over with only the current ones
+                self.atlas.clear();
+                self.build_instances(queue, &glyphs, origin).unwrap_or_else(|| {
+                    log::warn!("Glyph atlas is too small for {:?}, some glyphs are skipped", self.text);
+                    vec![]
+                })
+            },
+        };
+
+        if instances.len() * std::mem::size_of::<GlyphInstance>() > self.instance_buffer.size() as usize {
+            self.instance_buffer = create_instance_buffer(device, instances.len().next_power_of_two());
+        }
+
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        self.instance_count = instances.len() as u32;
+    }
+
+    /// `None` if the atlas ran out of space
+    fn build_instances(&mut self, queue: &wgpu::Queue, glyphs: &[PositionedGlyph], origin: [f32; 2]) -> Option<Vec<GlyphInstance>> {
+        let mut instances = Vec::with_capacity(glyphs.len());
+        let raster_scale = self.raster_scale();
+        // Distance fields are stretched from `SDF_SIZE` to the font size, large bitmaps from the largest raster size
+        let factor = self.scale.y / raster_scale.y;
+
+        for glyph in glyphs {
+            let key = GlyphKey {
+                font_index: glyph.font_index,
+                glyph: glyph.glyph,
+                scale_bits: raster_scale.y.to_bits(),
+            };
+
+            let atlas_glyph = match self.atlas.get(queue, &self.fonts, key) {
+                AtlasEntry::Cached(glyph) => glyph,
+                AtlasEntry::Empty => continue,
+                AtlasEntry::Full => return None,
+            };
+
+            instances.push(GlyphInstance {
+                rect: [
+                    origin[0] + glyph.pos[0].round() + atlas_glyph.offset[0] * factor,
+                    origin[1] + glyph.pos[1].round() + atlas_glyph.offset[1] * factor,
+                    atlas_glyph.size[0] * factor,
+                    atlas_glyph.size[1] * factor,
+                ],
+                uv_rect: atlas_glyph.uv_rect,
+            });
+        }
+
+        Some(instances)
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.quad_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        if self.mode == TextMode::Sdf {
+            render_pass.set_bind_group(2, &self.sdf_bind_group, &[]);
+        }
+        render_pass.draw(0..QUAD.len() as u32, 0..self.instance_count);
+    }
+}
@@ -0,0 +1,174 @@
+//! GPU time between points of a frame, from timestamp queries read back a few frames later
+//! so measuring never stalls the pipeline
+
+use std::{sync::mpsc, time::Duration};
+
+/// Frames whose timestamps can be on their way back at once, older ones are dropped
+pub const TIMER_FRAMES: usize = 3;
+
+enum ReadbackState {
+    Free,
+    /// Receives the timestamps of the frame being recorded
+    Recording,
+    Mapping {
+        frame: u64,
+        mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    },
+}
+
+struct TimerReadback {
+    buffer: wgpu::Buffer,
+    state: ReadbackState,
+}
+
+/// `markers` timestamps per frame, each written once all the work recorded before it is done
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    markers: u32,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<TimerReadback>,
+    /// Nanoseconds per timestamp tick
+    period: f64,
+    frame: u64,
+    /// Time between consecutive markers of the newest frame read back, and its number
+    latest: Option<(u64, Vec<Duration>)>,
+}
+
+impl GpuTimer {
+    pub fn is_supported(device: &wgpu::Device) -> bool {
+        device.features().contains(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    /// `None` if `device` wasn't opened with `Features::TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, markers: u32) -> Option<Self> {
+        if !Self::is_supported(device) || markers == 0 {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_timer_queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: markers,
+        });
+
+        let size = markers as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp resolve buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..TIMER_FRAMES)
+            .map(|_| TimerReadback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp readback buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: ReadbackState::Free,
+            })
+            .collect();
+
+        Some(GpuTimer {
+            query_set, markers, resolve_buffer, readbacks,
+            period: queue.get_timestamp_period() as f64,
+            frame: 0,
+            latest: None,
+        })
+    }
+
+    pub fn markers(&self) -> u32 {
+        self.markers
+    }
+
+    fn recording(&self) -> Option<usize> {
+        self.readbacks.iter().position(|r| matches!(r.state, ReadbackState::Recording))
+    }
+
+    /// Collects the frames read back so far and picks a buffer for the next one.
+    /// Without a free buffer the next frame isn't measured
+    pub fn begin_frame(&mut self) {
+        self.collect();
+
+        if self.recording().is_none()
+            && let Some(readback) = self.readbacks.iter_mut().find(|r| matches!(r.state, ReadbackState::Free))
+        {
+            readback.state = ReadbackState::Recording;
+        }
+    }
+
+    /// Writes timestamp `index` after everything recorded into `encoder` so far
+    pub fn write_marker(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        if self.recording().is_none() {
+            return;
+        }
+
+        // An empty pass, the timestamp at its end waits for the work before it to finish
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Timestamp marker"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: None,
+                end_of_pass_write_index: Some(index),
+            }),
+        });
+    }
+
+    /// Copies the timestamps of the frame out of the query set, after the last marker
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(recording) = self.recording() else { return };
+
+        encoder.resolve_query_set(&self.query_set, 0..self.markers, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readbacks[recording].buffer, 0, self.resolve_buffer.size());
+    }
+
+    /// Starts reading the frame back, once the command buffer `resolve` was recorded into is submitted
+    pub fn frame_submitted(&mut self) {
+        let Some(recording) = self.recording() else { return };
+
+        let (tx, rx) = mpsc::channel();
+        let readback = &mut self.readbacks[recording];
+        readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| { let _ = tx.send(res); });
+        readback.state = ReadbackState::Mapping { frame: self.frame, mapped: rx };
+        self.frame += 1;
+    }
+
+    fn collect(&mut self) {
+        for readback in &mut self.readbacks {
+            let ReadbackState::Mapping { frame, mapped } = &readback.state else { continue };
+
+            let frame = *frame;
+            let is_mapped = match mapped.try_recv() {
+                Ok(Ok(())) => true,
+                Ok(Err(e)) => {
+                    log::warn!("Unable to read the GPU timestamps: {e}");
+                    false
+                },
+                Err(mpsc::TryRecvError::Empty) => continue,
+                Err(mpsc::TryRecvError::Disconnected) => false,
+            };
+
+            if is_mapped {
+                let durations = {
+                    let data = readback.buffer.slice(..).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    timestamps.windows(2)
+                        .map(|t| Duration::from_nanos((t[1].saturating_sub(t[0]) as f64 * self.period) as u64))
+                        .collect()
+                };
+                readback.buffer.unmap();
+
+                if self.latest.as_ref().is_none_or(|(latest, _)| frame > *latest) {
+                    self.latest = Some((frame, durations));
+                }
+            }
+            readback.state = ReadbackState::Free;
+        }
+    }
+
+    /// Time between each pair of consecutive markers of the newest frame read back
+    pub fn durations(&self) -> Option<&[Duration]> {
+        self.latest.as_ref().map(|(_, durations)| durations.as_slice())
+    }
+}
@@ -0,0 +1,200 @@
+//! Last pass of the frame: maps the HDR image into the displayable range and encodes it to sRGB
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    graph::{Pass, PassContext},
+    hot_reload::Shader,
+    renderer::{texture_bind_group_layout, PipelineBuilder, PipelineError, Vertex, QUAD},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Clamp to 1
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+    Agx,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 4] = [Tonemapper::None, Tonemapper::Reinhard, Tonemapper::Aces, Tonemapper::Agx];
+
+    /// Matches the `TONEMAP_*` constants in `shader_tonemap.wgsl`
+    fn shader_id(self) -> u32 {
+        match self {
+            Tonemapper::None => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Aces => 2,
+            Tonemapper::Agx => 3,
+        }
+    }
+}
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct TonemapOptions {
+    /// Operator mapping HDR colors to the display
+    #[arg(long, value_enum, default_value_t)]
+    pub tonemap: Tonemapper,
+
+    /// Exposure applied before tonemapping, in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+}
+
+impl Default for TonemapOptions {
+    fn default() -> Self {
+        TonemapOptions {
+            tonemap: Tonemapper::default(),
+            exposure: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TonemapUniforms {
+    pub op: u32,
+    /// Linear multiplier
+    pub exposure: f32,
+    /// Non-zero makes the shader encode to sRGB, for output formats that don't
+    pub encode_srgb: u32,
+    pub _pad: u32,
+}
+
+impl TonemapUniforms {
+    pub fn new(options: &TonemapOptions, output_format: wgpu::TextureFormat) -> Self {
+        TonemapUniforms {
+            op: options.tonemap.shader_id(),
+            exposure: options.exposure.exp2(),
+            encode_srgb: !output_format.is_srgb() as u32,
+            _pad: 0,
+        }
+    }
+}
+
+pub fn tonemap_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("tonemap_bind_group_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn tonemap_pipeline(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    cache: Option<&wgpu::PipelineCache>,
+    layouts: &[wgpu::BindGroupLayout; 2],
+    source: &str,
+) -> Result<wgpu::RenderPipeline, PipelineError> {
+    PipelineBuilder::new(device, format, source)
+        .with_label(Shader::Tonemap.file_name())
+        .with_cache(cache)
+        .with_buffer(Vertex::desc())
+        .with_bind_group(&layouts[0])
+        .with_bind_group(&layouts[1])
+        .build()
+}
+
+/// Graph pass drawing its single HDR input into the output
+pub struct TonemapPass {
+    options: TonemapOptions,
+
+    pipeline: wgpu::RenderPipeline,
+    format: wgpu::TextureFormat,
+    cache: Option<wgpu::PipelineCache>,
+    layouts: [wgpu::BindGroupLayout; 2],
+
+    buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TonemapPass {
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        cache: Option<&wgpu::PipelineCache>,
+        options: TonemapOptions,
+    ) -> Result<Self, PipelineError> {
+        let layouts = [texture_bind_group_layout(device), tonemap_bind_group_layout(device)];
+        let pipeline = tonemap_pipeline(device, output_format, cache, &layouts, Shader::Tonemap.builtin())?;
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap vertex buffer"),
+            contents: bytemuck::cast_slice(QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap uniform buffer"),
+            contents: bytemuck::cast_slice(&[TonemapUniforms::new(&options, output_format)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_bind_group"),
+            layout: &layouts[1],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(TonemapPass {
+            options,
+            pipeline,
+            format: output_format,
+            cache: cache.cloned(),
+            layouts,
+            buffer, uniform_buffer, bind_group,
+        })
+    }
+
+    pub fn options(&self) -> &TonemapOptions {
+        &self.options
+    }
+
+    /// Applied on the next frame
+    pub fn set_options(&mut self, options: TonemapOptions) {
+        self.options = options;
+    }
+}
+
+impl Pass for TonemapPass {
+    fn prepare(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, _output_size: (u32, u32)) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[TonemapUniforms::new(&self.options, self.format)]));
+    }
+
+    fn record(&self, ctx: &mut PassContext) {
+        let input = ctx.inputs[0];
+        let mut render_pass = ctx.begin_render_pass();
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.set_bind_group(0, input, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..QUAD.len() as u32, 0..1);
+    }
+
+    fn reload_shader(&mut self, device: &wgpu::Device, shader: Shader, source: &str) -> Result<(), PipelineError> {
+        if shader == Shader::Tonemap {
+            self.pipeline = tonemap_pipeline(device, self.format, self.cache.as_ref(), &self.layouts, source)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use clap::Parser;
+use wgpu_test::{
+    adapter::{list_adapters, AdapterOptions, AdapterSelector, Backend, PowerPreference, DEFAULT_BACKENDS},
+    config::Config,
+    headless::{request_adapter, skip_without_adapter},
+};
+
+fn parse_config(args: &[&str]) -> Result<Config, clap::Error> {
+    Config::try_parse_from(std::iter::once("wgpu-test").chain(args.iter().copied()))
+}
+
+fn adapter(name: &str, backend: wgpu::Backend, device_type: wgpu::DeviceType) -> wgpu::AdapterInfo {
+    wgpu::AdapterInfo {
+        name: name.into(),
+        vendor: 0,
+        device: 0,
+        device_type,
+        driver: String::new(),
+        driver_info: String::new(),
+        backend,
+    }
+}
+
+fn adapters() -> Vec<wgpu::AdapterInfo> {
+    use wgpu::{Backend::*, DeviceType::*};
+
+    vec![
+        adapter("llvmpipe (LLVM 15.0.6, 256 bits)", Vulkan, Cpu),
+        adapter("Intel(R) UHD Graphics 620", Gl, IntegratedGpu),
+        adapter("Intel(R) UHD Graphics 620", Vulkan, IntegratedGpu),
+        adapter("NVIDIA GeForce RTX 3060", Vulkan, DiscreteGpu),
+    ]
+}
+
+#[test]
+fn options_are_parsed() {
+    let options = parse_config(&["--backend", "vulkan,gl", "--adapter", "NVIDIA", "--power-preference", "low"]).unwrap().adapter;
+    assert_eq!(options.backend, [Backend::Vulkan, Backend::Gl]);
+    assert_eq!(options.backends(), wgpu::Backends::VULKAN | wgpu::Backends::GL);
+    assert_eq!(options.adapter, Some(AdapterSelector::Name("nvidia".into())));
+    assert_eq!(options.power_preference, Some(PowerPreference::Low));
+
+    assert_eq!(parse_config(&["--adapter", "2"]).unwrap().adapter.adapter, Some(AdapterSelector::Index(2)));
+    assert_eq!(parse_config(&["--backend", "dx12"]).unwrap().adapter.backends(), wgpu::Backends::DX12);
+    // The names WGPU_BACKEND takes
+    assert_eq!(parse_config(&["--backend", "vk,mtl"]).unwrap().adapter.backends(), wgpu::Backends::VULKAN | wgpu::Backends::METAL);
+    assert!(parse_config(&["--list-adapters"]).unwrap().adapter.list_adapters);
+
+    assert!(parse_config(&["--backend", "glide"]).is_err());
+    assert!(parse_config(&["--adapter", ""]).is_err());
+}
+
+#[test]
+fn hardware_and_primary_backends_go_first() {
+    let adapters = adapters();
+    let options = AdapterOptions::default();
+    assert_eq!(options.backends(), DEFAULT_BACKENDS);
+    assert_eq!(options.choose(&adapters, |_| true).unwrap(), 2);
+    // Any GPU beats a software adapter, whatever the backend
+    assert_eq!(options.choose(&adapters, |i| i != 2 && i != 3).unwrap(), 1);
+    assert_eq!(options.choose(&adapters, |i| i == 0).unwrap(), 0);
+
+    let gl = AdapterOptions { backend: vec![Backend::Gl, Backend::Vulkan], ..Default::default() };
+    assert_eq!(gl.choose(&adapters, |_| true).unwrap(), 1);
+
+    let high = AdapterOptions { power_preference: Some(PowerPreference::High), ..Default::default() };
+    assert_eq!(high.choose(&adapters, |_| true).unwrap(), 3);
+    let low = AdapterOptions { power_preference: Some(PowerPreference::Low), ..Default::default() };
+    assert_eq!(low.choose(&adapters, |_| true).unwrap(), 2);
+    assert_eq!(low.choose(&adapters, |i| i != 1 && i != 2).unwrap(), 3);
+}
+
+#[test]
+fn adapter_is_picked_by_name_or_index() {
+    let adapters = adapters();
+    let by = |selector: &str| AdapterOptions { adapter: Some(AdapterSelector::parse(selector).unwrap()), ..Default::default() };
+
+    assert_eq!(by("0").choose(&adapters, |_| true).unwrap(), 0);
+    assert_eq!(by("geforce").choose(&adapters, |_| true).unwrap(), 3);
+    assert_eq!(by("LLVMpipe").choose(&adapters, |_| true).unwrap(), 0);
+    assert_eq!(by("intel").choose(&adapters, |_| true).unwrap(), 2);
+    assert_eq!(by("intel").choose(&adapters, |i| i != 2).unwrap(), 1);
+}
+
+#[test]
+fn nothing_suitable_is_an_error() {
+    let adapters = adapters();
+
+    let err = AdapterOptions::default().choose(&[], |_| true).unwrap_err().to_string();
+    assert!(err.starts_with("No adapters found"), "{err}");
+
+    let missing = AdapterOptions { adapter: Some(AdapterSelector::Name("radeon".into())), ..Default::default() };
+    let err = missing.choose(&adapters, |_| true).unwrap_err().to_string();
+    assert!(err.contains("--adapter \"radeon\""), "{err}");
+    assert!(err.contains("  3: NVIDIA GeForce RTX 3060 (Vulkan, DiscreteGpu)"), "{err}");
+
+    let out_of_range = AdapterOptions { adapter: Some(AdapterSelector::Index(4)), ..Default::default() };
+    assert!(out_of_range.choose(&adapters, |_| true).is_err());
+
+    // The listed indices stay those of --list-adapters
+    let nvidia = AdapterOptions { adapter: Some(AdapterSelector::Index(3)), ..Default::default() };
+    let err = nvidia.choose(&adapters, |i| i != 3).unwrap_err().to_string();
+    assert!(err.contains("can present to the window"), "{err}");
+    assert!(err.contains("  3: NVIDIA"), "{err}");
+    assert!(!err.contains("Intel"), "{err}");
+}
+
+#[test]
+fn adapters_are_listed() {
+    let adapter = match pollster::block_on(request_adapter(true)) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            skip_without_adapter("adapter listing", e);
+            return;
+        },
+    };
+
+    let text = list_adapters(std::slice::from_ref(&adapter));
+    let info = adapter.get_info();
+    assert!(text.starts_with(&format!("0: {}\n", info.name)), "{text}");
+    assert!(text.contains(&format!("  backend: {:?}\n", info.backend)), "{text}");
+    assert!(text.contains(&format!("    max_texture_dimension_2d: {}\n", adapter.limits().max_texture_dimension_2d)), "{text}");
+
+    assert_eq!(list_adapters(&[]), "No adapters found\n");
+}
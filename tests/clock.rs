@@ -0,0 +1,119 @@
+use clap::Parser;
+use wgpu_test::{
+    clock::{Clock, ClockOptions, DEFAULT_STEP, MAX_TICK},
+    config::Config,
+};
+
+use std::time::{Duration, Instant};
+
+fn parse_config(args: &[&str]) -> Result<Config, clap::Error> {
+    Config::try_parse_from(std::iter::once("wgpu-test").chain(args.iter().copied()))
+}
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn assert_time(clock: &Clock, expected: f64) {
+    assert!((clock.time() as f64 - expected).abs() < 1e-5, "{} instead of {expected}", clock.time());
+}
+
+#[test]
+fn clock_options_are_parsed() {
+    let config = parse_config(&["--time", "-2.5", "--speed", "-0.5", "--paused", "--timestep", "0.02"]).unwrap();
+    assert_eq!(config.clock.time, -2.5);
+    assert_eq!(config.clock.speed, -0.5);
+    assert!(config.clock.paused);
+    assert_eq!(config.clock.timestep, Some(0.02));
+
+    assert_eq!(parse_config(&[]).unwrap().clock.speed, 1.0);
+    assert_eq!(parse_config(&["--headless", "out.png", "--time", "3"]).unwrap().clock.time, 3.0);
+
+    for args in [&["--timestep", "0"][..], &["--timestep", "-1"], &["--speed", "inf"], &["--headless", "out.png", "--paused"]] {
+        assert!(parse_config(args).is_err(), "{args:?} was accepted");
+    }
+}
+
+#[test]
+fn clock_follows_the_wall_clock() {
+    let start = Instant::now();
+    let mut clock = Clock::new(&ClockOptions { time: 10.0, ..Default::default() });
+
+    // The first tick only sets the reference point
+    assert_eq!(clock.tick(start), 10.0);
+    clock.tick(start + ms(100));
+    assert_time(&clock, 10.1);
+    clock.tick(start + ms(350));
+    assert_time(&clock, 10.35);
+}
+
+#[test]
+fn stalls_are_clamped() {
+    let start = Instant::now();
+    let mut clock = Clock::new(&ClockOptions::default());
+    clock.tick(start);
+    clock.tick(start + Duration::from_secs(5));
+    assert_time(&clock, MAX_TICK.as_secs_f64());
+}
+
+#[test]
+fn pause_holds_the_time() {
+    let start = Instant::now();
+    let mut clock = Clock::new(&ClockOptions::default());
+    clock.tick(start);
+    clock.tick(start + ms(100));
+
+    assert!(clock.toggle_pause());
+    clock.tick(start + ms(200));
+    clock.tick(start + ms(300));
+    assert_time(&clock, 0.1);
+
+    // Resuming doesn't catch up with the time spent paused
+    assert!(!clock.toggle_pause());
+    clock.tick(start + ms(400));
+    assert_time(&clock, 0.2);
+}
+
+#[test]
+fn speed_scales_the_time() {
+    let start = Instant::now();
+    let mut clock = Clock::new(&ClockOptions { speed: 2.0, ..Default::default() });
+    clock.tick(start);
+    clock.tick(start + ms(100));
+    assert_time(&clock, 0.2);
+
+    clock.set_speed(-1.0);
+    clock.tick(start + ms(150));
+    assert_time(&clock, 0.15);
+}
+
+#[test]
+fn fixed_timestep_ignores_the_wall_clock() {
+    let start = Instant::now();
+    let mut clock = Clock::new(&ClockOptions { timestep: Some(0.5), speed: 0.5, ..Default::default() });
+    clock.tick(start);
+    clock.tick(start + ms(1));
+    clock.tick(start + Duration::from_secs(10));
+    assert_time(&clock, 0.5);
+}
+
+#[test]
+fn seek_step_and_restart() {
+    let mut clock = Clock::new(&ClockOptions { time: 1.0, ..Default::default() });
+    clock.seek(4.0);
+    clock.seek_by(-1.5);
+    assert_time(&clock, 2.5);
+
+    clock.step(3);
+    assert!(clock.is_paused());
+    assert_time(&clock, 2.5 + 3.0 * DEFAULT_STEP);
+    clock.step(-3);
+    assert_time(&clock, 2.5);
+
+    clock.restart();
+    assert_time(&clock, 1.0);
+
+    let mut clock = Clock::new(&ClockOptions { timestep: Some(0.25), ..Default::default() });
+    clock.step(2);
+    assert_time(&clock, 0.5);
+}
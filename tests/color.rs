@@ -0,0 +1,141 @@
+use clap::Parser;
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    config::Config,
+    color::{linear_to_srgb, srgb_to_linear, surface_format, LinearRgb, Srgb},
+    headless::{get_target_texture_with_format, read_texture, request_device, request_device_from, skip_without_adapter, Headless, HEADLESS_FORMAT, HEADLESS_SRGB_FORMAT},
+    palette::parse_color,
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+const SIZE: (u32, u32) = (96, 48);
+
+/// Flat background with the text on top, so nothing depends on the noise hash precision of a driver
+fn colors() -> Colors {
+    let bg = parse_color("#4080c0").unwrap();
+    Colors::new(bg, bg)
+}
+
+fn options() -> RenderOptions {
+    let mut options = RenderOptions::default();
+    options.text.font_size = 32.0;
+    options
+}
+
+fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+    a.as_raw().iter().zip(b.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0)
+}
+
+#[test]
+fn srgb_conversions_match_reference_values() {
+    assert_eq!(srgb_to_linear(0.0), 0.0);
+    assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+    assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-4);
+    assert!((srgb_to_linear(0.02) - 0.02 / 12.92).abs() < 1e-6);
+
+    for i in 0..=255 {
+        let c = i as f32 / 255.0;
+        assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5, "{c} doesn't survive the round trip");
+    }
+
+    let linear = Srgb([1.0, 0.5, 0.0]).to_linear();
+    assert_eq!(linear, LinearRgb([1.0, srgb_to_linear(0.5), 0.0]));
+    assert!((linear.to_srgb().0[1] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn surface_format_prefers_hardware_encoding() {
+    let caps = |formats: &[wgpu::TextureFormat]| wgpu::SurfaceCapabilities {
+        formats: formats.to_vec(),
+        present_modes: vec![],
+        alpha_modes: vec![],
+        usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    };
+    let views = wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS;
+
+    let format = surface_format(&caps(&[wgpu::TextureFormat::Bgra8Unorm, wgpu::TextureFormat::Bgra8UnormSrgb]), views);
+    assert_eq!(format.format, wgpu::TextureFormat::Bgra8UnormSrgb);
+    assert!(format.view_formats().is_empty());
+
+    let format = surface_format(&caps(&[wgpu::TextureFormat::Rgba8Unorm]), views);
+    assert_eq!(format.format, wgpu::TextureFormat::Rgba8Unorm);
+    assert_eq!(format.view_formats(), [wgpu::TextureFormat::Rgba8UnormSrgb]);
+    assert!(format.is_srgb());
+
+    // Without sRGB views the tonemapping shader has to encode
+    let format = surface_format(&caps(&[wgpu::TextureFormat::Rgba8Unorm]), wgpu::DownlevelFlags::empty());
+    assert_eq!(format.view_format, wgpu::TextureFormat::Rgba8Unorm);
+    assert!(!format.is_srgb());
+
+    let format = surface_format(&caps(&[wgpu::TextureFormat::Rgba16Float]), views);
+    assert!(format.view_formats().is_empty());
+}
+
+#[test]
+fn shader_encoding_matches_srgb_target() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("color test", e);
+            return;
+        },
+    };
+
+    let render = |format| {
+        let mut renderer = Renderer::new(&device, format, SIZE, colors(), &options()).unwrap();
+        let target = get_target_texture_with_format(&device, SIZE, format);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&device, &queue, &mut encoder, &view, 1.0);
+        queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&device, &queue, &target).unwrap()
+    };
+
+    let shader = render(HEADLESS_FORMAT);
+    let hardware = render(HEADLESS_SRGB_FORMAT);
+    assert!(max_difference(&shader, &hardware) <= 1);
+}
+
+#[test]
+fn sdf_text_color_is_srgb() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("color test", e);
+            return;
+        },
+    };
+
+    let args = ["wgpu-test", "--text", "H", "--font-size", "64", "--text-mode", "sdf", "--text-color", "#808080", "--tonemap", "none", "--post"];
+    let options = Config::try_parse_from(args).unwrap().render;
+    let black = Colors::new([0.0; 3], [0.0; 3]);
+
+    let mut headless = Headless::with_device(device, queue, SIZE, black, &options).unwrap();
+    let img = headless.render(1.0).unwrap();
+
+    // Inside the glyph the fill is exactly the given color, encoded once
+    let brightest = img.pixels().map(|p| p[0]).max().unwrap();
+    assert!(brightest.abs_diff(128) <= 2, "text is {brightest}, not 128");
+}
+
+/// Renders on the first adapter of `backends`
+fn render_on(backends: wgpu::Backends) -> anyhow::Result<RgbaImage> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor { backends, ..Default::default() });
+    let adapter = instance.enumerate_adapters(backends).into_iter().next()
+        .ok_or_else(|| anyhow::anyhow!("no {backends:?} adapter"))?;
+    let (device, queue) = pollster::block_on(request_device_from(&adapter))?;
+
+    let mut headless = Headless::with_device(device, queue, SIZE, colors(), &options())?;
+    headless.render(1.0)
+}
+
+/// Needs both a Vulkan and a GL adapter, which most machines don't have together:
+/// `cargo test --test color -- --ignored`
+#[test]
+#[ignore]
+fn same_palette_same_pixels_on_vulkan_and_gl() {
+    let vulkan = render_on(wgpu::Backends::VULKAN).unwrap();
+    let gl = render_on(wgpu::Backends::GL).unwrap();
+
+    assert!(max_difference(&vulkan, &gl) <= 1);
+}
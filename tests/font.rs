@@ -0,0 +1,36 @@
+use wgpu_test::font::FontChain;
+
+/// Returns `None` when no system font is installed, so the test is skipped instead of failing
+fn fonts(fonts: &[String]) -> Option<FontChain> {
+    match FontChain::load(fonts) {
+        Ok(fonts) => Some(fonts),
+        Err(e) => {
+            eprintln!("Skipping font test: {e}");
+            None
+        },
+    }
+}
+
+#[test]
+fn fallback_fonts_are_loaded_on_first_use() {
+    let Some(fonts) = fonts(&[]) else { return };
+    assert_eq!(fonts.loaded(), 1);
+
+    assert_eq!(fonts.glyph('a').0, 0);
+    assert_eq!(fonts.loaded(), 1);
+
+    // Unassigned, so every font is tried
+    let (index, glyph) = fonts.glyph('\u{0378}');
+    assert_eq!((index, glyph.0), (0, 0));
+    assert_eq!(fonts.loaded(), fonts.names().count());
+}
+
+#[test]
+fn faces_already_in_the_chain_are_skipped() {
+    let Some(fallbacks) = fonts(&[]) else { return };
+    let names: Vec<String> = fallbacks.names().map(String::from).collect();
+
+    // The primary fallback passed as --font isn't appended a second time
+    let fonts = fonts(&names[..1]).unwrap();
+    assert_eq!(fonts.names().collect::<Vec<_>>(), names);
+}
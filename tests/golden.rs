@@ -0,0 +1,476 @@
+//! Renders each shader with fixed inputs on a software adapter and compares the
+//! result against the reference images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to regenerate the references after an intended change.
+
+use imageproc::image::{Rgba, RgbaImage};
+use wgpu::util::DeviceExt;
+use wgpu_test::{
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter},
+    noise_lib::{with_noise_lib, NoiseBasis},
+    post::{Effect, EffectKind, EffectUniforms},
+    renderer::{
+        create_image_texture, text_blend_state, texture_bind_group_layout, uniform_bind_group_layout,
+        Colors, PipelineBuilder, Uniforms, Vertex,
+        NOISE_LIB_SHADER, POST_SHADER, QUAD, SCENE_SHADER, TEXT_SDF_SHADER, TEXT_SHADER, TONEMAP_SHADER,
+    },
+    sdf::{generate_sdf, sdf_params_bind_group_layout, SdfParams},
+    text::{text_uniform_bind_group_layout, GlyphInstance, TextUniforms},
+    tonemap::{tonemap_bind_group_layout, TonemapOptions, TonemapUniforms, Tonemapper},
+};
+
+use std::path::PathBuf;
+
+const SIZE: (u32, u32) = (160, 90);
+const TIME: f32 = 1.5;
+
+/// Maximum allowed difference of a single channel before a pixel counts as mismatched
+const TOLERANCE: u8 = 4;
+/// Fraction of mismatched pixels tolerated, to absorb hash precision differences between drivers
+const MAX_MISMATCHED: f64 = 0.002;
+
+const CLEAR_COLOR: wgpu::Color = wgpu::Color { r: 0.2, g: 0.4, b: 0.6, a: 1.0 };
+
+fn uniforms() -> Uniforms {
+    Uniforms { time: TIME, aspect: SIZE.0 as f32 / SIZE.1 as f32 }
+}
+
+fn colors() -> Colors {
+    Colors::new([0.05, 0.1, 0.2], [0.9, 0.7, 0.3])
+}
+
+/// Input texture for the text and post shaders: a soft-edged gradient disc on transparent black,
+/// premultiplied like the rasterized text
+fn pattern() -> RgbaImage {
+    RgbaImage::from_fn(64, 32, |x, y| {
+        let dx = x as f32 - 32.0;
+        let dy = (y as f32 - 16.0) * 2.0;
+        let alpha = (28.0 - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+        let premultiply = |c: f32| (c * alpha) as u8;
+        Rgba([premultiply(x as f32 * 4.0), premultiply(y as f32 * 8.0), premultiply(255.0), (alpha * 255.0) as u8])
+    })
+}
+
+/// Distance field of a ring, stored in the red channel like the glyph atlas
+fn sdf_pattern() -> RgbaImage {
+    let (w, h) = (48, 48);
+    let coverage: Vec<u8> = (0..w * h)
+        .map(|i| {
+            let dx = (i % w) as f32 + 0.5 - 24.0;
+            let dy = (i / w) as f32 + 0.5 - 24.0;
+            let r = (dx * dx + dy * dy).sqrt();
+            if (8.0..16.0).contains(&r) { 255 } else { 0 }
+        })
+        .collect();
+
+    let field = generate_sdf(&coverage, w, h, 8);
+    RgbaImage::from_fn(w, h, |x, y| {
+        let d = field[(y * w + x) as usize];
+        Rgba([d, d, d, 255])
+    })
+}
+
+struct Harness {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Harness {
+    /// Returns `None` when no software adapter exists and `skip_without_adapter` allows skipping the suite
+    fn new() -> Option<Self> {
+        match pollster::block_on(request_device(true)) {
+            Ok((device, queue)) => Some(Harness { device, queue }),
+            Err(e) => {
+                skip_without_adapter("golden test", e);
+                None
+            },
+        }
+    }
+
+    fn uniform_bind_group(&self, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        let uniform_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniforms()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let color_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color buffer"),
+            contents: bytemuck::cast_slice(&[colors()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("uniform_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: color_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn texture_bind_group(&self, layout: &wgpu::BindGroupLayout, img: &RgbaImage) -> wgpu::BindGroup {
+        let texture = create_image_texture(&self.device, &self.queue, "pattern_texture", img);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = self.device.create_sampler(&wgpu::wgt::SamplerDescriptor {
+            label: Some("pattern_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("texture_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    }
+
+    fn text_uniform_bind_group(&self, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Text uniform buffer"),
+            contents: bytemuck::cast_slice(&[TextUniforms::new([SIZE.0 as f32, SIZE.1 as f32], false)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("text_uniform_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn tonemap_bind_group(&self, layout: &wgpu::BindGroupLayout, uniforms: TonemapUniforms) -> wgpu::BindGroup {
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Layout and bind group of a single effect, without the dynamic offset the post stack uses
+    fn effect_bind_group(&self, effect: EffectUniforms) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let layout = self.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("effect_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Effect buffer"),
+            contents: bytemuck::cast_slice(&[effect]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("effect_bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (layout, bind_group)
+    }
+
+    fn sdf_params_bind_group(&self, layout: &wgpu::BindGroupLayout, params: SdfParams) -> wgpu::BindGroup {
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SDF params buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sdf_params_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Draws a fullscreen quad with `pipeline` over `CLEAR_COLOR` and reads the result back.
+    /// With `instances` the quad is drawn once per instance, bound at vertex buffer slot 1
+    fn draw(&self, pipeline: &wgpu::RenderPipeline, bind_groups: &[&wgpu::BindGroup], instances: Option<&[GlyphInstance]>) -> RgbaImage {
+        let target = get_target_texture(&self.device, SIZE);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex buffer"),
+            contents: bytemuck::cast_slice(QUAD),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let instance_buffer = instances.map(|instances| {
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance buffer"),
+                contents: bytemuck::cast_slice(instances),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+        let instance_count = instances.map_or(1, |instances| instances.len() as u32);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: Some("Golden encoder") });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Golden render pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(CLEAR_COLOR),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            if let Some(instance_buffer) = &instance_buffer {
+                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            }
+            for (i, group) in bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(i as u32, *group, &[]);
+            }
+            render_pass.draw(0..QUAD.len() as u32, 0..instance_count);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&self.device, &self.queue, &target).unwrap()
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Compares `actual` with `tests/golden/<name>.png`. On failure the actual image and a
+/// diff image (mismatched pixels in red) are written to `target/tmp/golden`
+fn assert_golden(name: &str, actual: &RgbaImage) {
+    let reference_path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = match imageproc::image::open(&reference_path) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => panic!("Unable to load {}: {e}. Run with UPDATE_GOLDEN=1 to create it", reference_path.display()),
+    };
+
+    assert_eq!(reference.dimensions(), actual.dimensions(), "{name}: size differs from reference");
+
+    let mut mismatched = 0;
+    let diff = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let r = reference.get_pixel(x, y);
+        let max_delta = a.0.iter().zip(r.0.iter()).map(|(a, r)| a.abs_diff(*r)).max().unwrap();
+
+        if max_delta > TOLERANCE {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([r[0] / 4, r[1] / 4, r[2] / 4, 255])
+        }
+    });
+
+    let total = (actual.width() * actual.height()) as f64;
+    if mismatched as f64 / total > MAX_MISMATCHED {
+        let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{name}.actual.png"));
+        let diff_path = out_dir.join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "{name}: {mismatched} of {total} pixels differ by more than {TOLERANCE}, see {} and {}",
+            actual_path.display(), diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn golden_scene_shader() {
+    let Some(h) = Harness::new() else { return };
+
+    let uniform_layout = uniform_bind_group_layout(&h.device);
+    let uniform_group = h.uniform_bind_group(&uniform_layout);
+    let source = with_noise_lib(SCENE_SHADER, NOISE_LIB_SHADER);
+
+    for basis in NoiseBasis::ALL {
+        let pipeline = PipelineBuilder::new(&h.device, wgpu::TextureFormat::Rgba8Unorm, &source)
+            .with_buffer(Vertex::desc())
+            .with_bind_group(&uniform_layout)
+            .with_constant("NOISE_BASIS", basis.shader_id() as f64)
+            .build()
+            .unwrap();
+
+        let name = format!("scene_{}", format!("{basis:?}").to_lowercase());
+        assert_golden(&name, &h.draw(&pipeline, &[&uniform_group], None));
+    }
+}
+
+#[test]
+fn golden_text_shader() {
+    let Some(h) = Harness::new() else { return };
+
+    let texture_layout = texture_bind_group_layout(&h.device);
+    let text_uniform_layout = text_uniform_bind_group_layout(&h.device);
+    let pipeline = PipelineBuilder::new(&h.device, wgpu::TextureFormat::Rgba8Unorm, TEXT_SHADER)
+        .with_buffer(Vertex::desc())
+        .with_buffer(GlyphInstance::desc())
+        .with_bind_group(&texture_layout)
+        .with_bind_group(&text_uniform_layout)
+        .with_blending(text_blend_state())
+        .build()
+        .unwrap();
+
+    // The pattern stands in for the glyph atlas: whole, and the left half stretched
+    let instances = [
+        GlyphInstance { rect: [8.0, 8.0, 64.0, 32.0], uv_rect: [0.0, 0.0, 1.0, 1.0] },
+        GlyphInstance { rect: [80.0, 20.0, 72.0, 64.0], uv_rect: [0.0, 0.0, 0.5, 1.0] },
+    ];
+
+    let texture_group = h.texture_bind_group(&texture_layout, &pattern());
+    let text_uniform_group = h.text_uniform_bind_group(&text_uniform_layout);
+    assert_golden("text", &h.draw(&pipeline, &[&texture_group, &text_uniform_group], Some(&instances)));
+}
+
+#[test]
+fn golden_text_sdf_shader() {
+    let Some(h) = Harness::new() else { return };
+
+    let texture_layout = texture_bind_group_layout(&h.device);
+    let text_uniform_layout = text_uniform_bind_group_layout(&h.device);
+    let sdf_layout = sdf_params_bind_group_layout(&h.device);
+    let pipeline = PipelineBuilder::new(&h.device, wgpu::TextureFormat::Rgba8Unorm, TEXT_SDF_SHADER)
+        .with_buffer(Vertex::desc())
+        .with_buffer(GlyphInstance::desc())
+        .with_bind_group(&texture_layout)
+        .with_bind_group(&text_uniform_layout)
+        .with_bind_group(&sdf_layout)
+        .with_blending(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+        .build()
+        .unwrap();
+
+    // Rasterized at 48px and drawn at 1.5x, so 8 texels of spread cover 12 screen pixels
+    let params = SdfParams {
+        fill_color: [1.0, 0.9, 0.2, 1.0],
+        outline_color: [0.8, 0.1, 0.1, 1.0],
+        shadow_color: [0.0, 0.0, 0.0, 0.8],
+        glow_color: [0.2, 1.0, 1.0, 1.0],
+        shadow_offset: [4.0, 4.0],
+        outline_width: 2.0,
+        glow_width: 6.0,
+        shadow_softness: 2.0,
+        ..Default::default()
+    }.clamped(72.0 * 64.0 / 48.0);
+
+    let instances = [
+        GlyphInstance { rect: [4.0, 9.0, 72.0, 72.0], uv_rect: [0.0, 0.0, 1.0, 1.0] },
+        GlyphInstance { rect: [96.0, 29.0, 36.0, 36.0], uv_rect: [0.0, 0.0, 1.0, 1.0] },
+    ];
+
+    let texture_group = h.texture_bind_group(&texture_layout, &sdf_pattern());
+    let text_uniform_group = h.text_uniform_bind_group(&text_uniform_layout);
+    let sdf_group = h.sdf_params_bind_group(&sdf_layout, params);
+    assert_golden("text_sdf", &h.draw(&pipeline, &[&texture_group, &text_uniform_group, &sdf_group], Some(&instances)));
+}
+
+#[test]
+fn golden_post_shader() {
+    let Some(h) = Harness::new() else { return };
+
+    let uniform_layout = uniform_bind_group_layout(&h.device);
+    let texture_layout = texture_bind_group_layout(&h.device);
+    let effect = EffectUniforms { flip_y: 1, ..EffectUniforms::new(&Effect::new(EffectKind::Warp), SIZE) };
+    let (effect_layout, effect_group) = h.effect_bind_group(effect);
+    let pipeline = PipelineBuilder::new(&h.device, wgpu::TextureFormat::Rgba8Unorm, POST_SHADER)
+        .with_buffer(Vertex::desc())
+        .with_bind_group(&uniform_layout)
+        .with_bind_group(&texture_layout)
+        .with_bind_group(&effect_layout)
+        .build()
+        .unwrap();
+
+    let uniform_group = h.uniform_bind_group(&uniform_layout);
+    let texture_group = h.texture_bind_group(&texture_layout, &pattern());
+    assert_golden("post", &h.draw(&pipeline, &[&uniform_group, &texture_group, &effect_group], None));
+}
+
+#[test]
+fn golden_tonemap_shader() {
+    let Some(h) = Harness::new() else { return };
+
+    let texture_layout = texture_bind_group_layout(&h.device);
+    let tonemap_layout = tonemap_bind_group_layout(&h.device);
+    let pipeline = PipelineBuilder::new(&h.device, wgpu::TextureFormat::Rgba8Unorm, TONEMAP_SHADER)
+        .with_buffer(Vertex::desc())
+        .with_bind_group(&texture_layout)
+        .with_bind_group(&tonemap_layout)
+        .build()
+        .unwrap();
+
+    // Two stops up, so the brighter half of the pattern goes past 1
+    let options = TonemapOptions { tonemap: Tonemapper::Aces, exposure: 2.0 };
+    let texture_group = h.texture_bind_group(&texture_layout, &pattern());
+    let tonemap_group = h.tonemap_bind_group(&tonemap_layout, TonemapUniforms::new(&options, wgpu::TextureFormat::Rgba8Unorm));
+    assert_golden("tonemap", &h.draw(&pipeline, &[&texture_group, &tonemap_group], None));
+}
@@ -0,0 +1,114 @@
+use wgpu_test::{
+    graph::{Attachment, Pass, PassContext, PassDesc, RenderGraph, TargetDesc, TargetId},
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter},
+};
+
+/// Only clears its output, as declared in its `PassDesc`
+struct ClearPass;
+
+impl Pass for ClearPass {
+    fn record(&self, ctx: &mut PassContext) {
+        ctx.begin_render_pass();
+    }
+}
+
+/// Returns `None` when no software adapter exists and `skip_without_adapter` allows skipping the test
+fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    match pollster::block_on(request_device(true)) {
+        Ok(device) => Some(device),
+        Err(e) => {
+            skip_without_adapter("render graph test", e);
+            None
+        },
+    }
+}
+
+fn target(graph: &mut RenderGraph, device: &wgpu::Device, label: &str, scale: f32) -> TargetId {
+    graph.add_target(device, TargetDesc {
+        label: label.into(),
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        scale,
+        sample_count: 1,
+    })
+}
+
+fn pass(label: &str, inputs: &[TargetId], output: Attachment) -> PassDesc {
+    PassDesc {
+        label: label.into(),
+        inputs: inputs.to_vec(),
+        output,
+        clear: Some(wgpu::Color::BLACK),
+    }
+}
+
+#[test]
+fn passes_are_ordered_by_dependencies() {
+    let Some((device, _queue)) = device() else { return };
+
+    let mut graph = RenderGraph::new((64, 32));
+    let scene = target(&mut graph, &device, "scene", 1.0);
+    let blurred = target(&mut graph, &device, "blurred", 0.5);
+
+    graph.add_pass(pass("post", &[scene, blurred], Attachment::Output), ClearPass).unwrap();
+    graph.add_pass(pass("blur", &[scene], Attachment::Target(blurred)), ClearPass).unwrap();
+    graph.add_pass(pass("scene", &[], Attachment::Target(scene)), ClearPass).unwrap();
+    graph.add_pass(pass("overlay", &[], Attachment::Target(scene)), ClearPass).unwrap();
+
+    assert_eq!(graph.pass_order(), ["scene", "overlay", "blur", "post"]);
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let Some((device, _queue)) = device() else { return };
+
+    let mut graph = RenderGraph::new((64, 32));
+    let a = target(&mut graph, &device, "a", 1.0);
+    let b = target(&mut graph, &device, "b", 1.0);
+
+    graph.add_pass(pass("a_to_b", &[a], Attachment::Target(b)), ClearPass).unwrap();
+    assert!(graph.add_pass(pass("b_to_a", &[b], Attachment::Target(a)), ClearPass).is_err());
+    assert!(graph.add_pass(pass("a_to_a", &[a], Attachment::Target(a)), ClearPass).is_err());
+
+    assert_eq!(graph.pass_order(), ["a_to_b"]);
+}
+
+#[test]
+fn targets_follow_output_size() {
+    let Some((device, _queue)) = device() else { return };
+
+    let mut graph = RenderGraph::new((64, 32));
+    let full = target(&mut graph, &device, "full", 1.0);
+    let quarter = target(&mut graph, &device, "quarter", 0.25);
+
+    assert_eq!(graph.target_texture(quarter).size().width, 16);
+
+    graph.resize(&device, (100, 2));
+    assert_eq!((graph.target_texture(full).width(), graph.target_texture(full).height()), (100, 2));
+    assert_eq!((graph.target_texture(quarter).width(), graph.target_texture(quarter).height()), (25, 1));
+}
+
+#[test]
+fn execute_draws_into_output() {
+    let Some((device, queue)) = device() else { return };
+
+    let mut graph = RenderGraph::new((8, 8));
+    graph.add_pass(
+        PassDesc {
+            label: "clear".into(),
+            inputs: vec![],
+            output: Attachment::Output,
+            clear: Some(wgpu::Color { r: 1.0, g: 0.0, b: 1.0, a: 1.0 }),
+        },
+        ClearPass,
+    ).unwrap();
+
+    let output = get_target_texture(&device, (8, 8));
+    let view = output.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    graph.prepare(&device, &queue);
+    graph.execute(&mut encoder, &view);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let img = read_texture(&device, &queue, &output).unwrap();
+    assert!(img.pixels().all(|p| p.0 == [255, 0, 255, 255]));
+}
@@ -0,0 +1,117 @@
+use wgpu_test::{
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter, HEADLESS_FORMAT},
+    hot_reload::{Shader, ShaderWatcher},
+    noise::NoiseMode,
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const BROKEN_SHADER: &str = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(oops);\n}\n";
+
+/// Empty directory under the cargo target dir, unique per test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("hot_reload").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn watcher_reports_changed_shaders() {
+    let dir = temp_dir("watcher");
+    let watcher = ShaderWatcher::new(&dir).unwrap();
+
+    std::fs::write(dir.join("unrelated.txt"), "").unwrap();
+    std::fs::write(watcher.path(Shader::Post), Shader::Post.builtin()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut changed = vec![];
+    while changed.is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+        changed = watcher.changed();
+    }
+
+    assert_eq!(changed, vec![Shader::Post]);
+    assert_eq!(watcher.load(Shader::Post).unwrap(), Shader::Post.builtin());
+}
+
+#[test]
+fn broken_shader_keeps_previous_pipeline() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("hot reload test", e);
+            return;
+        },
+    };
+
+    let size = (64, 32);
+    let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+    let mut renderer = match Renderer::new(&device, HEADLESS_FORMAT, size, colors, &RenderOptions::default()) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            eprintln!("Skipping hot reload test: {e}");
+            return;
+        },
+    };
+
+    let render = |renderer: &mut Renderer| {
+        let target = get_target_texture(&device, size);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&device, &queue, &mut encoder, &view, 1.0);
+        queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&device, &queue, &target).unwrap()
+    };
+
+    let before = render(&mut renderer);
+
+    // The SDF text shader is ignored in the default bitmap text mode
+    for shader in [Shader::Scene, Shader::NoiseLib, Shader::Text, Shader::Post, Shader::Bloom, Shader::Tonemap] {
+        assert!(renderer.reload_shader(&device, shader, BROKEN_SHADER).is_err(), "{shader:?} accepted a broken shader");
+    }
+    assert_eq!(render(&mut renderer), before);
+
+    for shader in Shader::ALL {
+        renderer.reload_shader(&device, shader, shader.builtin()).unwrap();
+    }
+    assert_eq!(render(&mut renderer), before);
+}
+
+#[test]
+fn noise_shader_loaded_before_the_volume_is_used() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("hot reload test", e);
+            return;
+        },
+    };
+
+    let size = (64, 32);
+    let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+    let render = |renderer: &mut Renderer| {
+        renderer.set_noise_mode(&device, NoiseMode::Volume).unwrap();
+        let target = get_target_texture(&device, size);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&device, &queue, &mut encoder, &view, 1.0);
+        queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&device, &queue, &target).unwrap()
+    };
+
+    let mut builtin = Renderer::new(&device, HEADLESS_FORMAT, size, colors, &RenderOptions::default()).unwrap();
+    let builtin = render(&mut builtin);
+
+    // Loaded at startup in the analytic mode, the volume is only built by the first render
+    let flat = Shader::Noise.builtin().replace("return out_val / f32(VOLUME_OCTAVES);", "return 0.0;");
+    let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, size, colors, &RenderOptions::default()).unwrap();
+    renderer.reload_shader(&device, Shader::Noise, &flat).unwrap();
+    assert!(renderer.reload_shader(&device, Shader::Noise, BROKEN_SHADER).is_err());
+
+    assert_ne!(render(&mut renderer), builtin, "the volume was built from the builtin noise shader");
+}
@@ -0,0 +1,116 @@
+use ab_glyph::PxScale;
+use wgpu_test::{
+    font::FontChain,
+    layout::{layout, Align, Layout, LayoutOptions},
+};
+
+/// Returns `None` when no system font is installed, so the test is skipped instead of failing
+fn fonts() -> Option<FontChain> {
+    match FontChain::load(&[]) {
+        Ok(fonts) => Some(fonts),
+        Err(e) => {
+            eprintln!("Skipping layout test: {e}");
+            None
+        },
+    }
+}
+
+const SCALE: PxScale = PxScale { x: 32.0, y: 32.0 };
+
+fn line_right_edge(layout: &Layout, line: usize) -> f32 {
+    layout.glyphs.iter()
+        .filter(|g| g.line == line)
+        .map(|g| g.pos[0] + g.advance)
+        .fold(0.0, f32::max)
+}
+
+fn line_left_edge(layout: &Layout, line: usize) -> f32 {
+    layout.glyphs.iter()
+        .filter(|g| g.line == line)
+        .map(|g| g.pos[0])
+        .fold(f32::MAX, f32::min)
+}
+
+#[test]
+fn newlines_start_new_lines() {
+    let Some(fonts) = fonts() else { return };
+
+    let single = layout(&fonts, "ab", SCALE, &LayoutOptions::default());
+    let multi = layout(&fonts, "ab\ncd\r\nef", SCALE, &LayoutOptions::default());
+
+    assert_eq!(single.line_count, 1);
+    assert_eq!(multi.line_count, 3);
+    assert!(multi.size[1] > single.size[1] * 2.0);
+
+    let baselines: Vec<f32> = (0..3)
+        .map(|line| multi.glyphs.iter().find(|g| g.line == line).unwrap().pos[1])
+        .collect();
+    assert!(baselines[0] < baselines[1] && baselines[1] < baselines[2]);
+    assert!((baselines[1] - baselines[0] - (baselines[2] - baselines[1])).abs() < 1e-3);
+}
+
+#[test]
+fn wraps_at_word_boundaries() {
+    let Some(fonts) = fonts() else { return };
+
+    let text = "lorem ipsum dolor sit amet consectetur";
+    let unwrapped = layout(&fonts, text, SCALE, &LayoutOptions::default());
+    let max_width = unwrapped.size[0] / 2.5;
+
+    let options = LayoutOptions { max_width: Some(max_width), align: Align::Left, ..Default::default() };
+    let wrapped = layout(&fonts, text, SCALE, &options);
+
+    assert!(wrapped.line_count >= 3);
+    assert!(wrapped.size[0] <= max_width + 1e-3);
+    for line in 0..wrapped.line_count {
+        assert!(line_left_edge(&wrapped, line).abs() < 1e-3, "line {line} should start at the left edge");
+    }
+    assert_eq!(wrapped.glyphs.len(), unwrapped.glyphs.len());
+
+    // Every line but the first starts with the first letter of a word
+    let chars: Vec<char> = text.chars().collect();
+    for line in 1..wrapped.line_count {
+        let first = wrapped.glyphs.iter().position(|g| g.line == line).unwrap();
+        assert_eq!(chars[first - 1], ' ', "line {line} starts in the middle of a word");
+    }
+}
+
+#[test]
+fn breaks_words_longer_than_a_line() {
+    let Some(fonts) = fonts() else { return };
+
+    let options = LayoutOptions { max_width: Some(SCALE.x * 2.0), ..Default::default() };
+    let wrapped = layout(&fonts, "abcdefghijklmnop", SCALE, &options);
+
+    assert!(wrapped.line_count > 1);
+    assert_eq!(wrapped.glyphs.len(), 16);
+}
+
+#[test]
+fn alignment() {
+    let Some(fonts) = fonts() else { return };
+
+    let text = "short\nmuch longer line";
+    let left = layout(&fonts, text, SCALE, &LayoutOptions { align: Align::Left, ..Default::default() });
+    let center = layout(&fonts, text, SCALE, &LayoutOptions { align: Align::Center, ..Default::default() });
+    let right = layout(&fonts, text, SCALE, &LayoutOptions { align: Align::Right, ..Default::default() });
+
+    let width = left.size[0];
+    assert!(line_left_edge(&left, 0).abs() < 1e-3);
+    assert!((line_right_edge(&right, 0) - width).abs() < 1e-3);
+
+    let center_gap_left = line_left_edge(&center, 0);
+    let center_gap_right = width - line_right_edge(&center, 0);
+    assert!((center_gap_left - center_gap_right).abs() < 1e-3);
+}
+
+#[test]
+fn line_spacing_scales_line_height() {
+    let Some(fonts) = fonts() else { return };
+
+    let normal = layout(&fonts, "a\nb", SCALE, &LayoutOptions::default());
+    let double = layout(&fonts, "a\nb", SCALE, &LayoutOptions { line_spacing: 2.0, ..Default::default() });
+
+    let gap = |l: &Layout| l.glyphs[1].pos[1] - l.glyphs[0].pos[1];
+    assert!((gap(&double) - 2.0 * gap(&normal)).abs() < 1e-3);
+}
@@ -0,0 +1,69 @@
+use clap::Parser;
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    config::Config,
+    headless::{get_target_texture, read_texture, request_adapter, request_device_from, skip_without_adapter, HEADLESS_FORMAT},
+    renderer::{Colors, Multisample, RenderOptions, Renderer, BACK_TEXTURE_FORMAT},
+};
+
+const SIZE: (u32, u32) = (64, 32);
+
+fn render_options(args: &[&str]) -> Result<RenderOptions, clap::Error> {
+    let args = std::iter::once("wgpu-test").chain(args.iter().copied());
+    Config::try_parse_from(args).map(|config| config.render)
+}
+
+fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+    a.as_raw().iter().zip(b.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0)
+}
+
+#[test]
+fn msaa_option_takes_powers_of_two() {
+    assert_eq!(render_options(&[]).unwrap().msaa, 1);
+    assert_eq!(render_options(&["--msaa", "8"]).unwrap().msaa, 8);
+
+    for count in ["0", "3", "16", "four"] {
+        assert!(render_options(&["--msaa", count]).is_err(), "{count} was accepted");
+    }
+}
+
+#[test]
+fn every_supported_sample_count_renders_the_same_scene() {
+    let adapter = match pollster::block_on(request_adapter(true)) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            skip_without_adapter("MSAA test", e);
+            return;
+        },
+    };
+    let (device, queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+
+    let render = |msaa| {
+        let options = RenderOptions { msaa, ..Default::default() }.with_adapter(&adapter);
+        let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+        let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, SIZE, colors, &options)?;
+
+        let target = get_target_texture(&device, SIZE);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&device, &queue, &mut encoder, &view, 1.0);
+        queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&device, &queue, &target)
+    };
+
+    let single = render(1).unwrap();
+
+    let features = RenderOptions::default().with_adapter(&adapter).scene_format_features;
+    let supported = Multisample::new(&device, BACK_TEXTURE_FORMAT, 1, features).supported_counts();
+    assert!(supported.contains(&4), "{supported:?}");
+
+    // The scene is a full screen quad, so the resolved samples agree with the single one
+    // everywhere but at the glyph edges
+    for count in supported.into_iter().filter(|&n| n > 1 && n <= 8) {
+        let multisampled = render(count).unwrap();
+        assert!(max_difference(&single, &multisampled) < 64, "{count}x differs too much");
+    }
+
+    let err = render(16).unwrap_err();
+    assert!(err.to_string().contains("16 samples"), "{err}");
+}
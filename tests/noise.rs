@@ -0,0 +1,100 @@
+use clap::Parser;
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    config::Config,
+    headless::{get_target_texture, read_texture, request_adapter, request_device_from, skip_without_adapter, HEADLESS_FORMAT},
+    noise::NoiseMode,
+    noise_lib::NoiseBasis,
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+const SIZE: (u32, u32) = (64, 64);
+
+fn render_options(args: &[&str]) -> Result<RenderOptions, clap::Error> {
+    let args = std::iter::once("wgpu-test").chain(args.iter().copied());
+    Config::try_parse_from(args).map(|config| config.render)
+}
+
+/// Share of pixels that aren't the background color
+fn coverage(img: &RgbaImage, bg: [u8; 3]) -> f32 {
+    let covered = img.pixels().filter(|p| p.0[..3].iter().zip(bg).any(|(a, b)| a.abs_diff(b) > 8)).count();
+    covered as f32 / (img.width() * img.height()) as f32
+}
+
+#[test]
+fn noise_options_parse() {
+    let options = render_options(&[]).unwrap();
+    assert_eq!(options.noise.noise, NoiseMode::Analytic);
+    assert_eq!(options.noise.noise_basis, NoiseBasis::Perlin);
+    assert_eq!(options.noise.noise_volume_size, 128);
+
+    let options = render_options(&["--noise", "volume", "--noise-basis", "worley", "--noise-volume-size", "64"]).unwrap();
+    assert_eq!(options.noise.noise, NoiseMode::Volume);
+    assert_eq!(options.noise.noise_basis, NoiseBasis::Worley);
+    assert_eq!(options.noise.noise_volume_size, 64);
+
+    for size in ["0", "12", "512", "big"] {
+        assert!(render_options(&["--noise-volume-size", size]).is_err(), "{size} was accepted");
+    }
+}
+
+#[test]
+fn volume_noise_matches_analytic_noise() {
+    let adapter = match pollster::block_on(request_adapter(true)) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            skip_without_adapter("noise test", e);
+            return;
+        },
+    };
+    let (device, queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+
+    // Black background and white noise, the text stays tiny in a corner of the frame
+    let options = render_options(&["--text", ".", "--font-size", "4", "--tonemap", "none"]).unwrap().with_adapter(&adapter);
+    let colors = Colors::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, SIZE, colors, &options).unwrap();
+
+    let render = |renderer: &mut Renderer| {
+        let target = get_target_texture(&device, SIZE);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&device, &queue, &mut encoder, &view, 10.0);
+        queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&device, &queue, &target).unwrap()
+    };
+
+    let analytic = render(&mut renderer);
+
+    renderer.set_noise_mode(&device, NoiseMode::Volume).unwrap();
+    assert_eq!(renderer.noise_mode(), NoiseMode::Volume);
+    let volume = render(&mut renderer);
+
+    // The volume is filtered, so it can't match pixel for pixel, but covers a similar share
+    let (analytic_coverage, volume_coverage) = (coverage(&analytic, [0; 3]), coverage(&volume, [0; 3]));
+    assert!(volume_coverage > 0.02, "volume noise is empty: {volume_coverage}");
+    assert!((analytic_coverage - volume_coverage).abs() < 0.15, "{analytic_coverage} vs {volume_coverage}");
+    assert_ne!(analytic, volume);
+
+    renderer.set_noise_mode(&device, NoiseMode::Analytic).unwrap();
+    assert_eq!(render(&mut renderer), analytic);
+}
+
+#[test]
+fn volume_rejects_noise_that_does_not_tile() {
+    let adapter = match pollster::block_on(request_adapter(true)) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            skip_without_adapter("noise test", e);
+            return;
+        },
+    };
+    let (device, _queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+
+    let options = render_options(&["--noise-basis", "simplex"]).unwrap().with_adapter(&adapter);
+    let colors = Colors::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+    let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, SIZE, colors, &options).unwrap();
+
+    let err = renderer.set_noise_mode(&device, NoiseMode::Volume).unwrap_err();
+    assert!(err.message.contains("doesn't tile"), "{err}");
+    assert_eq!(renderer.noise_mode(), NoiseMode::Analytic);
+}
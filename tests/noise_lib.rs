@@ -0,0 +1,198 @@
+use wgpu::util::DeviceExt;
+use wgpu_test::{
+    headless::{request_device, skip_without_adapter},
+    noise_lib::{
+        fade, hash_to_unit, hash_u32, noise_basis, perlin_noise, simplex_noise, value_noise, with_noise_lib, worley_noise,
+        NoiseBasis, Vec3,
+    },
+    renderer::NOISE_LIB_SHADER,
+};
+
+/// Evaluates every noise function of the library at `points`, `w` being the period
+const EVALUATE_SHADER: &str = "
+@group(0) @binding(0)
+var<storage, read> points: array<vec4<f32>>;
+
+@group(0) @binding(1)
+var<storage, read_write> noise: array<vec4<f32>>;
+
+@group(0) @binding(2)
+var<storage, read_write> hashes: array<u32>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= arrayLength(&points) {
+        return;
+    }
+
+    var p = points[id.x].xyz;
+    var period = i32(points[id.x].w);
+    noise[id.x] = vec4<f32>(value_noise(p, period), perlin_noise(p, period), simplex_noise(p), worley_noise(p, period));
+    hashes[id.x] = hash_u32(id.x);
+}
+";
+
+/// Differences between the CPU and GPU results, from the order of operations
+const TOLERANCE: f32 = 1e-4;
+
+/// Spread over negative and positive coordinates, every fourth one repeating every 4 cells
+fn sample_points() -> Vec<[f32; 4]> {
+    (0..256u32)
+        .map(|i| {
+            let h = hash_u32(i);
+            let coord = |h: u32| hash_to_unit(h) * 64.0 - 32.0;
+            let period = if i % 4 == 0 { 4.0 } else { 0.0 };
+            [coord(h), coord(hash_u32(h)), coord(hash_u32(hash_u32(h))), period]
+        })
+        .collect()
+}
+
+fn cpu_noise(point: [f32; 4]) -> [f32; 4] {
+    let p = [point[0], point[1], point[2]];
+    let period = point[3] as i32;
+    [value_noise(p, period), perlin_noise(p, period), simplex_noise(p), worley_noise(p, period)]
+}
+
+fn read_buffer<T: bytemuck::Pod>(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<T> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    queue.submit(std::iter::once(encoder.finish()));
+
+    staging.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::PollType::Wait).unwrap();
+    bytemuck::cast_slice(&staging.slice(..).get_mapped_range()).to_vec()
+}
+
+#[test]
+fn gpu_matches_cpu_mirror() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("noise library test", e);
+            return;
+        },
+    };
+
+    let points = sample_points();
+    let points_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Points buffer"),
+        contents: bytemuck::cast_slice(&points),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output = |label, size| device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let noise_buffer = output("Noise buffer", points.len() as u64 * 16);
+    let hash_buffer = output("Hash buffer", points.len() as u64 * 4);
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("evaluate_noise"),
+        source: wgpu::ShaderSource::Wgsl(with_noise_lib(EVALUATE_SHADER, NOISE_LIB_SHADER).into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("evaluate_noise"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("cs_main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry { binding: 0, resource: points_buffer.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 1, resource: noise_buffer.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 2, resource: hash_buffer.as_entire_binding() },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups((points.len() as u32).div_ceil(64), 1, 1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let hashes: Vec<u32> = read_buffer(&device, &queue, &hash_buffer);
+    for (i, &hash) in hashes.iter().enumerate() {
+        assert_eq!(hash, hash_u32(i as u32), "hash of {i}");
+    }
+
+    let noise: Vec<[f32; 4]> = read_buffer(&device, &queue, &noise_buffer);
+    let names = ["value", "perlin", "simplex", "worley"];
+    for (point, gpu) in points.iter().zip(noise) {
+        let cpu = cpu_noise(*point);
+        for i in 0..4 {
+            assert!((gpu[i] - cpu[i]).abs() < TOLERANCE, "{} noise at {point:?}: GPU {} vs CPU {}", names[i], gpu[i], cpu[i]);
+        }
+    }
+}
+
+#[test]
+fn fade_is_flat_at_the_lattice_points() {
+    assert_eq!(fade(0.0), 0.0);
+    assert_eq!(fade(1.0), 1.0);
+    assert_eq!(fade(0.5), 0.5);
+
+    // First and second derivatives vanish at both ends, so it leaves them like a cubic
+    for h in [1e-2, 1e-1] {
+        let bound = 10.0 * h * h * h * 1.01;
+        assert!(fade(h) <= bound, "{} at {h}", fade(h));
+        assert!(1.0 - fade(1.0 - h) <= bound, "{} at {}", fade(1.0 - h), 1.0 - h);
+    }
+}
+
+#[test]
+fn perlin_noise_is_zero_at_the_lattice_points() {
+    for p in [[0.0, 0.0, 0.0], [3.0, -7.0, 12.0], [-1.0, 5.0, -20.0]] {
+        assert_eq!(perlin_noise(p, 0), 0.0, "{p:?}");
+    }
+}
+
+#[test]
+fn noise_stays_in_range() {
+    for point in sample_points() {
+        let [value, perlin, simplex, worley] = cpu_noise(point);
+        assert!((0.0..=1.0).contains(&value), "value {value}");
+        assert!((-1.0..=1.0).contains(&perlin), "perlin {perlin}");
+        assert!((-1.0..=1.0).contains(&simplex), "simplex {simplex}");
+        assert!((0.0..=3f32.sqrt()).contains(&worley), "worley {worley}");
+    }
+}
+
+#[test]
+fn tiling_noise_repeats_after_the_period() {
+    let period = 5;
+    for point in sample_points() {
+        let p: Vec3 = [point[0], point[1], point[2]];
+        let shifted: Vec3 = [p[0] + period as f32, p[1] - period as f32, p[2] + 2.0 * period as f32];
+
+        for basis in NoiseBasis::ALL.into_iter().filter(|b| b.tiles()) {
+            let (a, b) = (noise_basis(basis, p, period), noise_basis(basis, shifted, period));
+            assert!((a - b).abs() < 1e-4, "{basis:?} at {p:?}: {a} vs {b}");
+        }
+    }
+}
+
+#[test]
+fn hash_is_well_spread() {
+    // Neighboring inputs land in every part of the range
+    let mut buckets = [0u32; 16];
+    for i in 0..16 * 256 {
+        buckets[(hash_u32(i) >> 28) as usize] += 1;
+    }
+    assert!(buckets.iter().all(|&n| (192..=320).contains(&n)), "{buckets:?}");
+}
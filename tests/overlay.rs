@@ -0,0 +1,122 @@
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter, HEADLESS_FORMAT},
+    overlay::{pass_name, FrameStats, GRAPH_WIDTH, HISTORY},
+    renderer::{Colors, RenderOptions, Renderer},
+    timing::GpuTimer,
+};
+
+use std::time::Duration;
+
+const SIZE: (u32, u32) = (256, 192);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn stats_average_over_the_history() {
+    let mut stats = FrameStats::default();
+    assert_eq!(stats.fps(), 0.0);
+    assert_eq!(stats.cpu_time(), Duration::ZERO);
+
+    // Only the last HISTORY frames count
+    for _ in 0..HISTORY {
+        stats.push(ms(100), ms(50));
+    }
+    for _ in 0..HISTORY {
+        stats.push(ms(20), ms(5));
+    }
+
+    assert!((stats.fps() - 50.0).abs() < 1e-9, "{}", stats.fps());
+    assert_eq!(stats.frame_time(), ms(20));
+    assert_eq!(stats.cpu_time(), ms(5));
+}
+
+#[test]
+fn graph_scales_to_the_longest_frame() {
+    let mut stats = FrameStats::default();
+    assert_eq!(stats.graph(GRAPH_WIDTH), (String::new(), Duration::ZERO));
+
+    for interval in [40, 10, 20, 0, 40] {
+        stats.push(ms(interval), ms(1));
+    }
+    assert_eq!(stats.graph(GRAPH_WIDTH), ("█▃▅▁█".into(), ms(40)));
+    assert_eq!(stats.graph(2), ("▁█".into(), ms(40)));
+}
+
+#[test]
+fn gpu_times_are_smoothed_per_pass() {
+    assert_eq!(pass_name("Scene render pass"), "scene");
+    assert_eq!(pass_name("bloom"), "bloom");
+
+    let mut stats = FrameStats::default();
+    assert!(stats.text().contains("n/a"));
+
+    stats.push_gpu_times([("Scene render pass", ms(2)), ("Post render pass", ms(1))]);
+    stats.push_gpu_times([("Scene render pass", ms(12)), ("Post render pass", ms(1))]);
+    assert_eq!(stats.gpu_times(), [("scene".to_string(), 3.0), ("post".to_string(), 1.0)]);
+
+    let text = stats.text();
+    assert!(text.contains("  scene      3.00 ms\n"), "{text}");
+    assert!(text.lines().any(|line| line.starts_with("gpu") && line.ends_with(" 4.00 ms")), "{text}");
+
+    // Different passes start over
+    stats.push_gpu_times([("Scene render pass", ms(5))]);
+    assert_eq!(stats.gpu_times(), [("scene".to_string(), 5.0)]);
+}
+
+fn render(renderer: &mut Renderer, device: &wgpu::Device, queue: &wgpu::Queue) -> RgbaImage {
+    let target = get_target_texture(device, SIZE);
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    renderer.render(device, queue, &mut encoder, &view, 1.0);
+    queue.submit(std::iter::once(encoder.finish()));
+    renderer.frame_submitted();
+    read_texture(device, queue, &target).unwrap()
+}
+
+#[test]
+fn overlay_is_drawn_and_times_every_pass() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("overlay test", e);
+            return;
+        },
+    };
+
+    let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+    let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, SIZE, colors, &RenderOptions::default()).unwrap();
+    let without = render(&mut renderer, &device, &queue);
+
+    renderer.set_overlay_visible(&device, true).unwrap();
+    assert!(renderer.overlay_visible());
+    renderer.set_overlay_text("fps 60.0\ncpu 1.00 ms");
+    let with = render(&mut renderer, &device, &queue);
+
+    let differs = |x0: u32, y0: u32| (x0..x0 + 64).any(|x| (y0..y0 + 32).any(|y| with.get_pixel(x, y) != without.get_pixel(x, y)));
+    assert!(differs(0, 0), "the overlay isn't drawn in the corner");
+    assert!(!differs(SIZE.0 - 64, SIZE.1 - 32), "the overlay covers more than the corner");
+
+    if GpuTimer::is_supported(&device) {
+        // Read back a few frames later, each render waits for its frame
+        let mut timings = None;
+        for _ in 0..4 {
+            render(&mut renderer, &device, &queue);
+            timings = renderer.pass_timings().map(|t| t.into_iter().map(|(label, time)| (label.to_string(), time)).collect::<Vec<_>>());
+        }
+
+        let timings = timings.expect("no pass timings were read back");
+        let labels: Vec<&str> = timings.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, renderer.pass_order());
+        assert_eq!(labels.last(), Some(&"Overlay render pass"));
+        assert!(timings.iter().all(|(_, time)| *time < Duration::from_secs(10)), "{timings:?}");
+    } else {
+        eprintln!("Skipping pass timings, the adapter has no timestamp queries");
+    }
+
+    renderer.set_overlay_visible(&device, false).unwrap();
+    assert!(renderer.pass_timings().is_none());
+    assert_eq!(render(&mut renderer, &device, &queue), without);
+}
@@ -0,0 +1,68 @@
+use clap::Parser;
+use wgpu::PresentMode::{Fifo, FifoRelaxed, Immediate, Mailbox};
+use wgpu_test::{
+    config::Config,
+    pacing::{FramePacer, Pacing, PresentMode},
+};
+
+use std::time::{Duration, Instant};
+
+fn parse_config(args: &[&str]) -> Result<Config, clap::Error> {
+    Config::try_parse_from(std::iter::once("wgpu-test").chain(args.iter().copied()))
+}
+
+#[test]
+fn pacing_is_parsed() {
+    assert_eq!(parse_config(&[]).unwrap().pacing.pacing, Pacing::Vsync);
+    assert_eq!(parse_config(&["--pacing", "uncapped"]).unwrap().pacing.pacing, Pacing::Uncapped);
+    assert_eq!(parse_config(&["--pacing", "144"]).unwrap().pacing.pacing, Pacing::Target(144.0));
+
+    for pacing in ["0", "-30", "inf", "fast"] {
+        assert!(parse_config(&["--pacing", pacing]).is_err(), "{pacing} was accepted");
+    }
+}
+
+#[test]
+fn present_mode_follows_the_pacing() {
+    let all = [Fifo, Mailbox, Immediate];
+    assert_eq!(PresentMode::Auto.choose(Pacing::Vsync, &all), Fifo);
+    assert_eq!(PresentMode::Auto.choose(Pacing::Uncapped, &all), Mailbox);
+    assert_eq!(PresentMode::Auto.choose(Pacing::Target(30.0), &all), Mailbox);
+    assert_eq!(PresentMode::Immediate.choose(Pacing::Vsync, &all), Immediate);
+}
+
+#[test]
+fn present_mode_falls_back() {
+    assert_eq!(PresentMode::Mailbox.choose(Pacing::Uncapped, &[Fifo, Immediate]), Immediate);
+    assert_eq!(PresentMode::Immediate.choose(Pacing::Uncapped, &[Fifo, Mailbox]), Mailbox);
+    assert_eq!(PresentMode::Auto.choose(Pacing::Uncapped, &[Fifo]), Fifo);
+
+    // Surfaces missing Fifo don't follow the spec, anything they report beats nothing
+    assert_eq!(PresentMode::Fifo.choose(Pacing::Vsync, &[FifoRelaxed]), FifoRelaxed);
+    assert_eq!(PresentMode::Fifo.choose(Pacing::Vsync, &[]), Fifo);
+}
+
+#[test]
+fn vsync_and_uncapped_draw_right_away() {
+    for pacing in [Pacing::Vsync, Pacing::Uncapped] {
+        let mut pacer = FramePacer::new(pacing);
+        assert_eq!(pacer.frame_presented(Instant::now()), None);
+    }
+}
+
+#[test]
+fn target_rate_keeps_to_the_schedule() {
+    let period = Duration::from_millis(20);
+    let start = Instant::now();
+    let mut pacer = FramePacer::new(Pacing::Target(50.0));
+
+    assert_eq!(pacer.frame_presented(start), Some(start + period));
+    // A frame finished early or late within its period doesn't shift the next one
+    assert_eq!(pacer.frame_presented(start + Duration::from_millis(25)), Some(start + 2 * period));
+    assert_eq!(pacer.frame_presented(start + Duration::from_millis(41)), Some(start + 3 * period));
+
+    // Missed frames aren't caught up on, the schedule restarts from now
+    let late = start + Duration::from_millis(200);
+    assert_eq!(pacer.frame_presented(late), Some(late));
+    assert_eq!(pacer.frame_presented(late + Duration::from_millis(1)), Some(late + period));
+}
@@ -0,0 +1,57 @@
+use wgpu_test::palette::{parse_color, seeded_colors, PaletteOptions};
+
+fn assert_color_eq(actual: [f32; 3], expected: [f32; 3]) {
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-3, "{actual:?} != {expected:?}");
+    }
+}
+
+#[test]
+fn same_seed_same_palette() {
+    let a = seeded_colors(42);
+    let b = seeded_colors(42);
+    let c = seeded_colors(43);
+
+    assert_eq!(a.bg_color, b.bg_color);
+    assert_eq!(a.fg_color, b.fg_color);
+    assert_ne!(a.bg_color, c.bg_color);
+
+    // Pinned so a seed keeps giving the palette it gave before
+    assert_color_eq(a.bg_color, [0.1212, 0.1682, 0.0318]);
+    assert_color_eq(a.fg_color, [0.9851, 0.9157, 0.4149]);
+}
+
+#[test]
+fn explicit_colors_override_seed() {
+    let options = PaletteOptions {
+        seed: Some(7),
+        bg: Some([0.0, 0.0, 1.0]),
+        fg: None,
+    };
+    let colors = options.resolve();
+
+    assert_eq!(colors.bg_color, [0.0, 0.0, 1.0]);
+    assert_eq!(colors.fg_color, seeded_colors(7).fg_color);
+}
+
+#[test]
+fn parse_hex() {
+    assert_color_eq(parse_color("#ff8000").unwrap(), [1.0, 128.0 / 255.0, 0.0]);
+    assert_color_eq(parse_color("00ff00").unwrap(), [0.0, 1.0, 0.0]);
+    assert_color_eq(parse_color("#f0a").unwrap(), [1.0, 0.0, 170.0 / 255.0]);
+}
+
+#[test]
+fn parse_hsl() {
+    assert_color_eq(parse_color("hsl(0, 100%, 50%)").unwrap(), [1.0, 0.0, 0.0]);
+    assert_color_eq(parse_color("hsl(240deg, 100%, 50%)").unwrap(), [0.0, 0.0, 1.0]);
+    assert_color_eq(parse_color("hsl(120, 0, 20)").unwrap(), [0.2, 0.2, 0.2]);
+}
+
+#[test]
+fn parse_invalid() {
+    assert!(parse_color("#12345").is_err());
+    assert!(parse_color("#gggggg").is_err());
+    assert!(parse_color("hsl(10, 50%)").is_err());
+    assert!(parse_color("hsl(10, 150%, 50%)").is_err());
+}
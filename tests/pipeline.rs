@@ -0,0 +1,239 @@
+use imageproc::image::RgbaImage;
+use wgpu::util::DeviceExt;
+use wgpu_test::{
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter, HEADLESS_FORMAT},
+    hot_reload::Shader,
+    noise_lib::with_noise_lib,
+    renderer::{validate_shader, Multisample, PipelineBuilder, PipelineError, Vertex, QUAD},
+};
+
+const PARSE_ERROR: &str = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    let = 1.0;\n    return vec4<f32>(1.0);\n}\n";
+
+const TYPE_ERROR: &str = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    let x: f32 = 1u;\n    return vec4<f32>(x);\n}\n";
+
+/// Valid on its own, but has no `vs_main` for the pipeline
+const MISSING_ENTRY_POINT: &str = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0);\n}\n";
+
+/// Vertex stage only, the fragment stage comes from `TARGETS_FRAGMENT`
+const TRIANGLE_VERTEX: &str = "
+struct VertexIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+override depth: f32 = 0.5;
+
+@vertex
+fn vs_triangle(in: VertexIn) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(in.pos, depth, 1.0);
+}
+";
+
+const TARGETS_FRAGMENT: &str = "
+struct Targets {
+    @location(0) first: vec4<f32>,
+    @location(1) second: vec4<f32>,
+};
+
+override red: f32 = 0.0;
+
+@fragment
+fn fs_targets() -> Targets {
+    return Targets(vec4<f32>(red, 0.0, 0.0, 1.0), vec4<f32>(0.0, 1.0, 0.0, 1.0));
+}
+";
+
+#[test]
+fn builtin_shaders_are_valid() {
+    for shader in Shader::ALL {
+        let source = if shader.uses_noise_lib() {
+            with_noise_lib(shader.builtin(), Shader::NoiseLib.builtin())
+        } else {
+            shader.builtin().into()
+        };
+        if let Err(e) = validate_shader(shader.file_name(), &source) {
+            panic!("{}", e.report);
+        }
+    }
+}
+
+#[test]
+fn parse_error_has_span() {
+    let err = validate_shader("broken.wgsl", PARSE_ERROR).unwrap_err();
+    let span = err.span.expect("parse errors point at the source");
+
+    assert_eq!(err.label, "broken.wgsl");
+    assert_eq!(span.line, 3);
+    assert!(err.to_string().starts_with("broken.wgsl:3:"), "{err}");
+    assert!(err.report.contains("broken.wgsl:3:"), "{}", err.report);
+}
+
+#[test]
+fn validation_error_has_span() {
+    let err = validate_shader("types.wgsl", TYPE_ERROR).unwrap_err();
+
+    assert_eq!(err.span.map(|s| s.line), Some(3), "{}", err.report);
+    assert!(err.report.contains("types.wgsl:3:"), "{}", err.report);
+}
+
+#[test]
+fn pipeline_error_is_returned() {
+    let (device, _queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("pipeline test", e);
+            return;
+        },
+    };
+
+    let err = PipelineBuilder::new(&device, HEADLESS_FORMAT, MISSING_ENTRY_POINT)
+        .with_label("fragment_only.wgsl")
+        .with_buffer(Vertex::desc())
+        .build()
+        .unwrap_err();
+
+    assert_eq!(err.label, "fragment_only.wgsl");
+    assert!(err.span.is_none());
+    assert!(err.message.contains("vs_main"), "{err}");
+
+    let err = PipelineBuilder::new(&device, HEADLESS_FORMAT, PARSE_ERROR)
+        .build()
+        .unwrap_err();
+    assert_eq!(err.span.map(|s| s.line), Some(3));
+}
+
+#[test]
+fn unsupported_sample_count_is_rejected() {
+    let (device, _queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("pipeline test", e);
+            return;
+        },
+    };
+
+    // WebGPU only guarantees 1 and 4 samples
+    let multisample = Multisample::new(&device, HEADLESS_FORMAT, 3, None);
+    assert_eq!(multisample.supported_counts(), [1, 4]);
+
+    let source = with_noise_lib(Shader::Scene.builtin(), Shader::NoiseLib.builtin());
+    let err = PipelineBuilder::new(&device, HEADLESS_FORMAT, &source)
+        .with_label("msaa.wgsl")
+        .with_buffer(Vertex::desc())
+        .with_multisample(multisample)
+        .build()
+        .unwrap_err();
+
+    assert_eq!(err.label, "msaa.wgsl");
+    assert!(err.message.contains("3 samples"), "{err}");
+}
+
+const SIZE: (u32, u32) = (16, 16);
+
+/// Draws the first triangle of `QUAD`, which covers the bottom left half of the targets and is wound
+/// clockwise, into two color targets and a depth buffer cleared to `depth_clear`
+fn draw_triangle(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    depth_clear: f32,
+    configure: &dyn for<'a> Fn(PipelineBuilder<'a>) -> PipelineBuilder<'a>,
+) -> Result<[RgbaImage; 2], PipelineError> {
+    let builder = PipelineBuilder::new(device, HEADLESS_FORMAT, TRIANGLE_VERTEX)
+        .with_label("triangle.wgsl")
+        .with_fragment_shader("targets.wgsl", TARGETS_FRAGMENT)
+        .with_entry_points("vs_triangle", "fs_targets")
+        .with_buffer(Vertex::desc())
+        .with_color_target(HEADLESS_FORMAT, None)
+        .with_topology(wgpu::PrimitiveTopology::TriangleList)
+        .with_depth_stencil(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        })
+        .with_constant("red", 1.0);
+    let pipeline = configure(builder).build()?;
+
+    let targets = [get_target_texture(device, SIZE), get_target_texture(device, SIZE)];
+    let views = targets.each_ref().map(|t| t.create_view(&wgpu::TextureViewDescriptor::default()));
+    let depth = device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some("depth"),
+        size: wgpu::Extent3d { width: SIZE.0, height: SIZE.1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(QUAD),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    {
+        let color_attachment = |view| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[color_attachment(&views[0]), color_attachment(&views[1])],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(depth_clear), store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    Ok(targets.map(|t| read_texture(device, queue, &t).unwrap()))
+}
+
+/// Whether the bottom left corner was drawn, the top right one never is
+fn drawn(images: &[RgbaImage; 2]) -> bool {
+    assert!(images.iter().all(|img| img.get_pixel(SIZE.0 - 1, 0).0 == [0; 4]));
+    let corners = images.each_ref().map(|img| img.get_pixel(0, SIZE.1 - 1).0);
+    match corners {
+        [[255, 0, 0, 255], [0, 255, 0, 255]] => true,
+        [[0, 0, 0, 0], [0, 0, 0, 0]] => false,
+        corners => panic!("unexpected colors {corners:?}"),
+    }
+}
+
+#[test]
+fn builder_options_reach_the_pipeline() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("pipeline test", e);
+            return;
+        },
+    };
+    let draw = |depth_clear, configure| drawn(&draw_triangle(&device, &queue, depth_clear, configure).unwrap());
+
+    // Both targets, with the overridden red in the first one
+    assert!(draw(1.0, &|b| b));
+
+    assert!(!draw(1.0, &|b| b.with_culling(wgpu::FrontFace::Ccw, wgpu::Face::Back)));
+    assert!(draw(1.0, &|b| b.with_culling(wgpu::FrontFace::Cw, wgpu::Face::Back)));
+
+    // The triangle is at a depth of 0.5 unless overridden
+    assert!(!draw(0.4, &|b| b));
+    assert!(draw(0.4, &|b| b.with_constant("depth", 0.3)));
+
+    let err = draw_triangle(&device, &queue, 1.0, &|b| b.with_entry_points("vs_triangle", "fs_main")).unwrap_err();
+    assert!(err.message.contains("fs_main"), "{err}");
+}
@@ -0,0 +1,90 @@
+use wgpu_test::{
+    headless::{request_device_from, HEADLESS_FORMAT},
+    pipeline_cache::{cache_file_name, DiskPipelineCache},
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+use std::path::PathBuf;
+
+/// Empty directory under the cargo target dir, unique per test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("pipeline_cache").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn adapter_info(backend: wgpu::Backend, driver_info: &str) -> wgpu::AdapterInfo {
+    wgpu::AdapterInfo {
+        name: "Test GPU".into(),
+        vendor: 0x10de,
+        device: 0x2684,
+        device_type: wgpu::DeviceType::DiscreteGpu,
+        driver: "NVIDIA".into(),
+        driver_info: driver_info.into(),
+        backend,
+    }
+}
+
+#[test]
+fn cache_file_depends_on_adapter_and_driver() {
+    let name = cache_file_name(&adapter_info(wgpu::Backend::Vulkan, "550.54.14")).unwrap();
+    assert_eq!(name, "wgpu_pipeline_cache_vulkan_4318_9860_NVIDIA_550.54.14.bin");
+
+    let updated = cache_file_name(&adapter_info(wgpu::Backend::Vulkan, "555.42.02")).unwrap();
+    assert_ne!(updated, name);
+
+    // Whatever the driver reports, the name stays a single path component
+    let odd = cache_file_name(&adapter_info(wgpu::Backend::Vulkan, "1.2/../3 beta")).unwrap();
+    assert!(odd.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)), "{odd}");
+
+    assert_eq!(cache_file_name(&adapter_info(wgpu::Backend::Gl, "550.54.14")), None);
+}
+
+#[test]
+fn cache_survives_a_restart() {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let Some(adapter) = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .find(|a| a.features().contains(wgpu::Features::PIPELINE_CACHE))
+    else {
+        eprintln!("Skipping pipeline cache test, no adapter supports it");
+        return;
+    };
+
+    let dir = temp_dir("restart");
+    let run = || {
+        let (device, _queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+        let cache = DiskPipelineCache::load(&device, &adapter.get_info(), &dir).expect("cache is supported");
+
+        let options = RenderOptions { pipeline_cache: Some(cache.cache().clone()), ..Default::default() };
+        let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+        Renderer::new(&device, HEADLESS_FORMAT, (64, 32), colors, &options).unwrap();
+
+        cache.save().unwrap();
+        std::fs::read(cache.path()).unwrap()
+    };
+
+    let first = run();
+    assert!(!first.is_empty());
+    assert!(!run().is_empty());
+}
+
+#[test]
+fn unsupported_backend_has_no_cache() {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let Some(adapter) = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .find(|a| !a.features().contains(wgpu::Features::PIPELINE_CACHE))
+    else {
+        eprintln!("Skipping pipeline cache test, every adapter supports it");
+        return;
+    };
+
+    let dir = temp_dir("unsupported");
+    let (device, _queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+    assert!(DiskPipelineCache::load(&device, &adapter.get_info(), &dir).is_none());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
@@ -0,0 +1,162 @@
+use clap::Parser;
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    bloom::{bloom_levels, MAX_BLOOM_LEVELS},
+    config::Config,
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter, HEADLESS_FORMAT},
+    post::{EffectKind, PostOptions},
+    renderer::{Colors, RenderOptions, Renderer},
+};
+
+fn post_options(args: &[&str]) -> Result<PostOptions, clap::Error> {
+    let args = std::iter::once("wgpu-test").chain(args.iter().copied());
+    Config::try_parse_from(args).map(|config| config.render.post)
+}
+
+fn enabled(options: &PostOptions) -> Vec<EffectKind> {
+    options.effects().iter().filter(|e| e.enabled).map(|e| e.kind).collect()
+}
+
+#[test]
+fn warp_is_the_default() {
+    let options = post_options(&[]).unwrap();
+    assert_eq!(enabled(&options), [EffectKind::Warp]);
+    assert_eq!(options.effects().len(), EffectKind::ALL.len());
+}
+
+#[test]
+fn effects_keep_requested_order() {
+    let options = post_options(&["--post", "vignette,bloom", "--post", "warp,bloom"]).unwrap();
+    assert_eq!(enabled(&options), [EffectKind::Vignette, EffectKind::Bloom, EffectKind::Warp]);
+
+    let effects = options.effects();
+    assert_eq!(effects[..3].iter().map(|e| e.kind).collect::<Vec<_>>(), enabled(&options));
+    assert!(effects[3..].iter().all(|e| !e.enabled));
+
+    let options = post_options(&["--post"]).unwrap();
+    assert!(enabled(&options).is_empty());
+}
+
+#[test]
+fn params_override_defaults() {
+    let options = post_options(&["--post-param", "bloom.threshold=0.8", "--post-param", "color-grade.gamma=2.2"]).unwrap();
+    let effects = options.effects();
+    let effect = |kind| effects.iter().find(|e| e.kind == kind).unwrap();
+
+    assert_eq!(effect(EffectKind::Bloom).params, [0.8, 1.0, 1.0, 6.0]);
+    assert_eq!(effect(EffectKind::ColorGrade).params[3], 2.2);
+    assert_eq!(effect(EffectKind::Warp).params, EffectKind::Warp.default_params());
+}
+
+#[test]
+fn invalid_params_are_rejected() {
+    for param in ["bloom.threshold", "threshold=1", "glow.threshold=1", "bloom.size=1", "bloom.threshold=abc", "bloom.threshold=inf",
+        "color-grade.gamma=0", "color-grade.gamma=-2.2"] {
+        assert!(post_options(&["--post-param", param]).is_err(), "{param} was accepted");
+    }
+    assert!(post_options(&["--post", "glow"]).is_err());
+}
+
+#[test]
+fn bloom_levels_fit_the_input() {
+    assert_eq!(bloom_levels((1280, 720), 6.0), 6);
+    assert_eq!(bloom_levels((1280, 720), 0.0), 1);
+    assert_eq!(bloom_levels((1280, 720), 100.0), MAX_BLOOM_LEVELS);
+    // Half of 32 is 16, down to 2x2 in 4 levels
+    assert_eq!(bloom_levels((64, 32), 6.0), 4);
+    assert_eq!(bloom_levels((1, 1), 6.0), 1);
+}
+
+struct Harness {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Harness {
+    const SIZE: (u32, u32) = (64, 32);
+
+    /// Returns `None` when no software adapter exists and `skip_without_adapter` allows skipping the test
+    fn new() -> Option<Self> {
+        match pollster::block_on(request_device(true)) {
+            Ok((device, queue)) => Some(Harness { device, queue }),
+            Err(e) => {
+                skip_without_adapter("post stack test", e);
+                None
+            },
+        }
+    }
+
+    fn renderer(&self, post: &[&str]) -> Renderer {
+        let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+        let options = RenderOptions {
+            post: post_options(post).unwrap(),
+            ..Default::default()
+        };
+        Renderer::new(&self.device, HEADLESS_FORMAT, Self::SIZE, colors, &options).unwrap()
+    }
+
+    fn render(&self, renderer: &mut Renderer) -> RgbaImage {
+        let target = get_target_texture(&self.device, Self::SIZE);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+        renderer.render(&self.device, &self.queue, &mut encoder, &view, 1.0);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        read_texture(&self.device, &self.queue, &target).unwrap()
+    }
+}
+
+fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+    a.as_raw().iter().zip(b.as_raw()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0)
+}
+
+fn brightness(img: &RgbaImage) -> u64 {
+    img.pixels().map(|p| p.0[..3].iter().map(|&c| c as u64).sum::<u64>()).sum()
+}
+
+#[test]
+fn bloom_adds_glow_above_threshold() {
+    let Some(h) = Harness::new() else { return };
+
+    let plain = h.render(&mut h.renderer(&["--post", "warp"]));
+
+    // The palette is no brighter than 1, so only the rounding of the HDR intermediate differs
+    let dark = h.render(&mut h.renderer(&["--post", "warp,bloom", "--post-param", "bloom.threshold=2"]));
+    assert!(max_difference(&dark, &plain) <= 1);
+
+    let bright = h.render(&mut h.renderer(&["--post", "warp,bloom", "--post-param", "bloom.threshold=0.1"]));
+    assert!(brightness(&bright) > brightness(&plain));
+
+    // Alone, bloom has to turn the upside down scene around like the plain copy does
+    let copy = h.render(&mut h.renderer(&["--post"]));
+    let first = h.render(&mut h.renderer(&["--post", "bloom", "--post-param", "bloom.threshold=2"]));
+    assert_eq!(first, copy);
+}
+
+#[test]
+fn toggling_effects_changes_the_output() {
+    let Some(h) = Harness::new() else { return };
+
+    let mut renderer = h.renderer(&["--post", "warp,vignette,scanlines,color-grade"]);
+    let render = |renderer: &mut Renderer| h.render(renderer);
+
+    let stacked = render(&mut renderer);
+
+    for kind in [EffectKind::Vignette, EffectKind::Scanlines, EffectKind::ColorGrade] {
+        assert!(!renderer.post_mut().toggle(kind));
+    }
+    let warp_only = render(&mut renderer);
+    assert_ne!(stacked, warp_only);
+
+    // The single remaining effect draws straight into the output, like the default stack
+    assert_eq!(warp_only, render(&mut h.renderer(&[])));
+
+    assert!(!renderer.post_mut().toggle(EffectKind::Warp));
+    assert!(renderer.post().effects().iter().all(|e| !e.enabled));
+    let copy = render(&mut renderer);
+    assert_ne!(copy, warp_only);
+
+    for kind in [EffectKind::Warp, EffectKind::Vignette, EffectKind::Scanlines, EffectKind::ColorGrade] {
+        assert!(renderer.post_mut().toggle(kind));
+    }
+    assert_eq!(render(&mut renderer), stacked);
+}
@@ -0,0 +1,119 @@
+use clap::Parser;
+use imageproc::image::{Rgba, RgbaImage};
+use wgpu_test::{
+    config::Config,
+    headless::{request_adapter, request_device_from, skip_without_adapter, Headless},
+    record::{record_frames, rgb_to_ycbcr, FrameWriter, RecordOptions, RecordOutput, Y4mWriter},
+    renderer::{Colors, RenderOptions},
+};
+
+use std::path::PathBuf;
+
+const SIZE: (u32, u32) = (32, 16);
+
+fn parse_config(args: &[&str]) -> Result<Config, clap::Error> {
+    Config::try_parse_from(std::iter::once("wgpu-test").chain(args.iter().copied()))
+}
+
+/// Keeps every frame in memory
+#[derive(Default)]
+struct Frames {
+    frames: Vec<RgbaImage>,
+    finished: bool,
+}
+
+impl FrameWriter for Frames {
+    fn write_frame(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        self.frames.push(frame.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.finished = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn record_output_is_parsed_from_the_path() {
+    assert_eq!(RecordOutput::parse("out.y4m"), Ok(RecordOutput::Y4m(PathBuf::from("out.y4m"))));
+
+    let sequence = RecordOutput::parse("frames/noise_%05d.png").unwrap();
+    assert_eq!(sequence.frame_path(42), Some(PathBuf::from("frames/noise_00042.png")));
+    assert_eq!(RecordOutput::parse("%d.PNG").unwrap().frame_path(7), Some(PathBuf::from("7.PNG")));
+
+    for path in ["out.mp4", "out.png", "out_%5d.png", "out_%d_%d.png", "out_%x.png"] {
+        assert!(RecordOutput::parse(path).is_err(), "{path} was accepted");
+    }
+}
+
+#[test]
+fn record_options_need_record() {
+    let config = parse_config(&["--record", "out.y4m", "--frames", "10", "--record-fps", "24", "--time", "2"]).unwrap();
+    assert_eq!(config.record.frames, 10);
+    assert_eq!(config.record.record_fps, 24);
+    assert_eq!(config.clock.time, 2.0);
+
+    assert!(parse_config(&["--frames", "10"]).is_err());
+    assert!(parse_config(&["--record", "out.y4m", "--headless", "out.png"]).is_err());
+    assert!(parse_config(&["--record", "out.y4m", "--fullscreen"]).is_err());
+    assert!(parse_config(&["--record", "out.y4m", "--frames", "0"]).is_err());
+}
+
+#[test]
+fn frames_advance_by_a_fixed_timestep() {
+    let options = RecordOptions { record_fps: 25, ..Default::default() };
+    assert_eq!(options.frame_time(1.0, 0), 1.0);
+    assert_eq!(options.frame_time(1.0, 25), 2.0);
+    assert_eq!(options.frame_time(0.0, 10), 0.4);
+}
+
+#[test]
+fn ycbcr_uses_the_limited_range() {
+    assert_eq!(rgb_to_ycbcr([0, 0, 0]), [16, 128, 128]);
+    assert_eq!(rgb_to_ycbcr([255, 255, 255]), [235, 128, 128]);
+    assert_eq!(rgb_to_ycbcr([255, 0, 0]), [81, 90, 240]);
+    assert_eq!(rgb_to_ycbcr([0, 0, 255]), [41, 240, 110]);
+}
+
+#[test]
+fn y4m_stream_has_a_header_and_planar_frames() {
+    let mut writer = Y4mWriter::new(Vec::new(), (2, 1), 30).unwrap();
+    let frame = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) });
+    writer.write_frame(&frame).unwrap();
+    writer.write_frame(&frame).unwrap();
+    assert!(writer.write_frame(&RgbaImage::new(1, 1)).is_err());
+    writer.finish().unwrap();
+
+    let frame_data = b"FRAME\n\x10\xeb\x80\x80\x80\x80";
+    let mut expected = b"YUV4MPEG2 W2 H1 F30:1 Ip A1:1 C444\n".to_vec();
+    expected.extend_from_slice(frame_data);
+    expected.extend_from_slice(frame_data);
+    assert_eq!(writer.into_inner(), expected);
+}
+
+#[test]
+fn recording_matches_single_frames() {
+    let adapter = match pollster::block_on(request_adapter(true)) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            skip_without_adapter("record test", e);
+            return;
+        },
+    };
+    let (device, queue) = pollster::block_on(request_device_from(&adapter)).unwrap();
+    let colors = Colors::new([0.1, 0.2, 0.3], [0.9, 0.8, 0.7]);
+    let mut headless = Headless::with_device(device, queue, SIZE, colors, &RenderOptions::default()).unwrap();
+
+    // More frames than are kept in flight, so staging buffers get reused
+    let options = RecordOptions { frames: 7, record_fps: 10, ..Default::default() };
+    let mut frames = Frames::default();
+    record_frames(&mut headless, &options, 0.5, &mut frames).unwrap();
+
+    assert!(frames.finished);
+    assert_eq!(frames.frames.len(), 7);
+    assert_ne!(frames.frames[0], frames.frames[1], "the animation didn't advance");
+    for (i, frame) in frames.frames.iter().enumerate() {
+        assert_eq!(*frame, headless.render(options.frame_time(0.5, i as u32)).unwrap(), "frame {i}");
+    }
+}
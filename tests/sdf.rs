@@ -0,0 +1,67 @@
+use wgpu_test::sdf::{generate_sdf, SdfParams, SDF_SIZE, SDF_SPREAD};
+
+/// 32x32 bitmap with a filled 16x16 square in the middle
+fn square() -> Vec<u8> {
+    (0..32 * 32)
+        .map(|i| {
+            let (x, y) = (i % 32, i / 32);
+            if (8..24).contains(&x) && (8..24).contains(&y) { 255 } else { 0 }
+        })
+        .collect()
+}
+
+#[test]
+fn sdf_edge_is_half() {
+    let field = generate_sdf(&square(), 32, 32, 4);
+    let at = |x: usize, y: usize| field[y * 32 + x];
+
+    // Pixels on both sides of the left edge are half a pixel away from it, 1/16 of the range
+    assert_eq!(at(8, 16), (0.5625f32 * 255.0).round() as u8);
+    assert_eq!(at(7, 16), (0.4375f32 * 255.0).round() as u8);
+}
+
+#[test]
+fn sdf_grows_inwards_and_saturates() {
+    let field = generate_sdf(&square(), 32, 32, 4);
+    let at = |x: usize, y: usize| field[y * 32 + x];
+
+    assert!(at(16, 16) > at(10, 16));
+    assert!(at(10, 16) > at(8, 16));
+    assert_eq!(at(16, 16), 255, "center is further than the spread from the edge");
+    assert_eq!(at(0, 0), 0, "corner is further than the spread from the edge");
+}
+
+#[test]
+fn sdf_is_euclidean() {
+    let field = generate_sdf(&square(), 32, 32, 8);
+
+    // Diagonally off the corner (7, 7), the nearest inside pixel is (8, 8)
+    let expected = 0.5 - (2.0f32.sqrt() - 0.5) / 16.0;
+    assert_eq!(field[7 * 32 + 7], (expected * 255.0).round() as u8);
+}
+
+#[test]
+fn sdf_empty_bitmap() {
+    assert!(generate_sdf(&[0; 16], 4, 4, 4).iter().all(|&d| d == 0));
+    assert!(generate_sdf(&[255; 16], 4, 4, 4).iter().all(|&d| d == 255));
+}
+
+#[test]
+fn sdf_params_clamped_to_spread() {
+    let font_size = SDF_SIZE * 2.0;
+    let max = SdfParams::max_effect_width(font_size);
+    assert_eq!(max, SDF_SPREAD as f32 * 2.0);
+
+    let params = SdfParams {
+        outline_width: 100.0,
+        glow_width: 4.0,
+        shadow_offset: [max * 3.0, max * 4.0],
+        ..Default::default()
+    }.clamped(font_size);
+
+    assert_eq!(params.outline_width, max);
+    assert_eq!(params.glow_width, 4.0);
+    assert!((params.shadow_offset[0] - max * 0.6).abs() < 1e-4);
+    assert!((params.shadow_offset[1] - max * 0.8).abs() < 1e-4);
+    assert_eq!(params.distance_scale, 2.0 * max);
+}
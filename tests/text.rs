@@ -0,0 +1,31 @@
+use clap::Parser;
+use wgpu_test::{
+    config::Config,
+    headless::{request_device, skip_without_adapter, Headless},
+    renderer::Colors,
+};
+
+const SIZE: (u32, u32) = (256, 256);
+
+#[test]
+fn largest_font_size_still_draws_text() {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("text test", e);
+            return;
+        },
+    };
+
+    // Three wide glyphs at this size don't fit the atlas together, the middle stem of Ш covers the center
+    let args = ["wgpu-test", "--text", "ЖШЩ", "--font-size", "2048", "--tonemap", "none", "--post"];
+    let options = Config::try_parse_from(args).unwrap().render;
+    let black = Colors::new([0.0; 3], [0.0; 3]);
+
+    let mut headless = Headless::with_device(device, queue, SIZE, black, &options).unwrap();
+    let img = headless.render(1.0).unwrap();
+
+    // All of them used to be dropped every frame, leaving the background
+    let center = img.get_pixel(SIZE.0 / 2, SIZE.1 / 2);
+    assert!(center[0] > 128, "the glyph isn't drawn: {center:?}");
+}
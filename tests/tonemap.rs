@@ -0,0 +1,74 @@
+use imageproc::image::RgbaImage;
+use wgpu_test::{
+    headless::{get_target_texture, read_texture, request_device, skip_without_adapter, HEADLESS_FORMAT},
+    post::PostOptions,
+    renderer::{Colors, RenderOptions, Renderer},
+    text::TextOptions,
+    tonemap::{TonemapOptions, Tonemapper},
+};
+
+const SIZE: (u32, u32) = (64, 32);
+
+/// Renders a flat `color` background without post effects, returning the top left pixel
+fn render(tonemap: TonemapOptions, color: [f32; 3]) -> Option<[u8; 4]> {
+    let (device, queue) = match pollster::block_on(request_device(true)) {
+        Ok(device) => device,
+        Err(e) => {
+            skip_without_adapter("tonemap test", e);
+            return None;
+        },
+    };
+
+    // Tiny text in the middle, away from the sampled corner
+    let options = RenderOptions {
+        text: TextOptions { font_size: 4.0, ..Default::default() },
+        post: PostOptions { post: vec![], post_param: vec![] },
+        tonemap,
+        ..Default::default()
+    };
+    let mut renderer = Renderer::new(&device, HEADLESS_FORMAT, SIZE, Colors::new(color, color), &options).unwrap();
+
+    let target = get_target_texture(&device, SIZE);
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(&wgpu::wgt::CommandEncoderDescriptor { label: None });
+    renderer.render(&device, &queue, &mut encoder, &view, 1.0);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let img: RgbaImage = read_texture(&device, &queue, &target).unwrap();
+    Some(img.get_pixel(0, 0).0)
+}
+
+fn assert_close(actual: [u8; 4], expected: [u8; 3]) {
+    for (a, e) in actual.iter().zip(expected) {
+        assert!(a.abs_diff(e) <= 1, "{actual:?} != {expected:?}");
+    }
+}
+
+#[test]
+fn palette_survives_the_linear_round_trip() {
+    let options = TonemapOptions { tonemap: Tonemapper::None, exposure: 0.0 };
+    let Some(pixel) = render(options, [0.2, 0.5, 0.8]) else { return };
+    assert_close(pixel, [51, 128, 204]);
+}
+
+#[test]
+fn exposure_brightens_in_linear_light() {
+    let options = TonemapOptions { tonemap: Tonemapper::None, exposure: 1.0 };
+    let Some(pixel) = render(options, [0.2, 0.5, 0.8]) else { return };
+
+    // 0.5 decodes to 0.214, doubled to 0.428 it encodes to 0.686, 0.8 clips
+    assert_close(pixel, [73, 175, 255]);
+}
+
+#[test]
+fn operators_compress_white() {
+    for tonemap in Tonemapper::ALL {
+        let options = TonemapOptions { tonemap, exposure: 0.0 };
+        let Some(pixel) = render(options, [1.0, 1.0, 1.0]) else { return };
+
+        match tonemap {
+            Tonemapper::None => assert_close(pixel, [255, 255, 255]),
+            _ => assert!(pixel[..3].iter().all(|&c| c > 128 && c < 255), "{tonemap:?} mapped white to {pixel:?}"),
+        }
+    }
+}